@@ -1,9 +1,14 @@
 use lib::eval_block;
 use lib::Context;
+use lib::Kind;
+use lib::Machine;
 use lib::Parser;
 use lib::Source;
 use lib::Stats;
+use lib::Token;
+use lib::Value;
 use lib::Writer;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::stdin;
 use std::io::BufRead;
@@ -46,6 +51,7 @@ pub fn eval(text: String, context: Option<Context>) -> Context {
     context
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn test(
     name: Option<String>,
     threads: u32,
@@ -54,9 +60,12 @@ pub fn test(
     path: Option<PathBuf>,
     record: Option<PathBuf>,
     stat: bool,
+    compile: bool,
+    options: Vec<(String, Value)>,
 ) {
     let text = read_to_string(path.unwrap_or(std::env::current_dir().unwrap()));
     let mut context = Context::new();
+    context.configure(options);
     let mut tests = match Parser::new(&text).parse() {
         Ok(Source { exprs, requests, tests }) => {
             context.extend(requests);
@@ -80,6 +89,7 @@ pub fn test(
             match tests.remove(&name) {
                 Some(test) => {
                     let name = Arc::new(name);
+                    let program = compile.then(|| Arc::new(lib::compile(&test, &context)));
                     let test = Arc::new(test);
                     let continuous = Arc::new(AtomicBool::new(true));
                     let maximun = number / threads;
@@ -90,10 +100,15 @@ pub fn test(
                         let test = test.to_owned();
                         let mut writer = writer(record.as_ref(), thread);
                         let mut context = context.to_owned();
+                        let mut machine = program.as_ref().map(|program| Machine::new(program.to_owned(), &context));
                         handles.push(std::thread::spawn(move || {
                             let mut number = u32::default();
                             while continuous.load(Ordering::Relaxed) && number < maximun {
-                                match eval_block(test.as_ref(), &mut context) {
+                                let result = match machine {
+                                    Some(ref mut machine) => machine.run(&mut context),
+                                    None => eval_block(test.as_ref(), &mut context),
+                                };
+                                match result {
                                     Ok(_) => {}
                                     Err(error) => {
                                         println!("{}", error);
@@ -127,8 +142,13 @@ pub fn test(
             for (thread, (name, test)) in tests.into_iter().enumerate() {
                 let mut writer = writer(record.as_ref(), thread as u32);
                 let mut context = context.to_owned();
+                let mut machine = compile.then(|| Machine::new(Arc::new(lib::compile(&test, &context)), &context));
                 handles.push(std::thread::spawn(move || {
-                    match eval_block(test.as_ref(), &mut context) {
+                    let result = match machine {
+                        Some(ref mut machine) => machine.run(&mut context),
+                        None => eval_block(test.as_ref(), &mut context),
+                    };
+                    match result {
                         Ok(_) => {}
                         Err(error) => {
                             println!("{}", error);
@@ -148,7 +168,8 @@ pub fn test(
             let mut stats = Stats::default();
             for records in receiver {
                 for record in records.iter() {
-                    stats.add(&record.name, record.time.total.as_millis());
+                    // the time throttling held a request back is told apart from its own
+                    stats.add(&record.name, record.time.total.as_millis(), record.time.throttle.as_millis());
                 }
             }
             print!("{}", stats);
@@ -160,6 +181,137 @@ pub fn test(
     }
 }
 
+pub fn check(path: Option<PathBuf>) -> bool {
+    let mut paths = Vec::new();
+    walk(path.unwrap_or(std::env::current_dir().unwrap()), &mut paths).expect("Could not read source file");
+    let mut problems = Vec::new();
+    let mut source = Source {
+        exprs: Vec::new(),
+        requests: HashMap::new(),
+        tests: HashMap::new(),
+    };
+    let mut texts = Vec::new();
+    for path in paths {
+        let text = std::fs::read_to_string(&path).expect("Could not read source file");
+        let mut parser = Parser::new(&text);
+        match parser.parse() {
+            Ok(parsed) => {
+                for (token, message) in duplicates(&parser, &text, &parsed, &source) {
+                    let at = token.map_or(path.display().to_string(), |token| position(&path, &token));
+                    problems.push(format!("{}: {}", at, message));
+                }
+                let Source { exprs, requests, tests } = parsed;
+                source.exprs.extend(exprs);
+                source.requests.extend(requests);
+                source.tests.extend(tests);
+            }
+            Err(error) => problems.push(format!("{}: {}", position(&path, parser.token()), error)),
+        }
+        texts.push((path, text));
+    }
+    for problem in lib::check(&source) {
+        let located = texts
+            .iter()
+            .find_map(|(path, text)| locate(text, &problem.scope, &problem.name).map(|token| position(path, &token)));
+        match located {
+            Some(at) => problems.push(format!("{}: {}", at, problem)),
+            None => problems.push(problem.to_string()),
+        }
+    }
+    problems.iter().for_each(|problem| println!("{}", problem));
+    problems.is_empty()
+}
+
+/// The requests and tests a parsed file defines more than once, or that the source read before it defines too,
+/// with the name token of each where it is found.
+pub fn duplicates(parser: &Parser, text: &str, parsed: &Source, source: &Source) -> Vec<(Option<Token>, String)> {
+    let mut problems = parser
+        .duplicates()
+        .iter()
+        .map(|(keyword, name)| {
+            let message = format!("{} {} is defined more than once", keyword.literal, name.literal);
+            (Some(name.clone()), message)
+        })
+        .collect::<Vec<_>>();
+    // names a file before this one defined too
+    let mut defined = parsed
+        .requests
+        .keys()
+        .filter(|name| source.requests.contains_key(*name))
+        .map(|name| ("request", name))
+        .chain(
+            parsed
+                .tests
+                .keys()
+                .filter(|name| source.tests.contains_key(*name))
+                .map(|name| ("test", name)),
+        )
+        .collect::<Vec<_>>();
+    defined.sort();
+    for (kind, name) in defined {
+        let located = locate(text, &format!("{} {}", kind, name), name);
+        problems.push((located, format!("{} {} is defined more than once", kind, name)));
+    }
+    problems
+}
+
+/// The place of a token as `path:line:column`.
+fn position(path: &std::path::Path, token: &Token) -> String {
+    format!("{}:{}:{}", path.display(), token.line, token.column)
+}
+
+/// Locates a checked name inside the scope it was reported for,
+/// for example `test call`, `request get` or `source` for the top level.
+pub fn locate(text: &str, scope: &str, name: &str) -> Option<Token> {
+    let tokens = lib::segment(text);
+    let start = match scope.split_once(' ') {
+        Some((kind, scope)) => tokens
+            .windows(2)
+            .position(|pair| pair[0].literal == kind && matches!(pair[0].kind, Kind::Request | Kind::Test) && pair[1].literal == scope)?,
+        None => 0,
+    };
+    tokens[start..].iter().find_map(|token| match token.kind {
+        Kind::Ident if token.literal == name => Some(token.clone()),
+        Kind::Template => {
+            let offset = token.literal.find(&format!("{{{}}}", name))?;
+            let before = &token.literal[..offset];
+            let line = token.line + before.matches('\n').count();
+            let column = match before.rfind('\n') {
+                Some(newline) => before[newline + 1..].chars().count() + 1,
+                None => token.column + before.chars().count() + 1,
+            };
+            Some(Token::new(Kind::Ident, format!("{{{}}}", name), line, column))
+        }
+        _ => None,
+    })
+}
+
+pub fn fmt(path: Option<PathBuf>, check: bool) -> bool {
+    let mut paths = Vec::new();
+    walk(path.unwrap_or(std::env::current_dir().unwrap()), &mut paths).expect("Could not read source file");
+    let mut success = true;
+    for path in paths {
+        let text = std::fs::read_to_string(&path).expect("Could not read source file");
+        match lib::format(&text) {
+            Ok(formatted) if formatted == text => {}
+            Ok(formatted) => {
+                if check {
+                    println!("Unformatted: {}", path.display());
+                    success = false;
+                } else if let Err(error) = std::fs::write(&path, formatted) {
+                    println!("Write file {} error: {:?}", path.display(), error);
+                    success = false;
+                }
+            }
+            Err(error) => {
+                println!("{}: {}", path.display(), error);
+                success = false;
+            }
+        }
+    }
+    success
+}
+
 fn read_to_string(path: PathBuf) -> String {
     let mut text = String::new();
     read(path, &mut text).expect("Could not read source file");
@@ -167,16 +319,25 @@ fn read_to_string(path: PathBuf) -> String {
 }
 
 fn read(path: PathBuf, text: &mut String) -> std::io::Result<()> {
+    let mut paths = Vec::new();
+    walk(path, &mut paths)?;
+    for path in paths {
+        text.push_str(&std::fs::read_to_string(path)?)
+    }
+    Ok(())
+}
+
+pub fn walk(path: PathBuf, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
     if path.is_dir() {
         let mut entries = std::fs::read_dir(path)?
             .map(|r| r.map(|e| e.path()))
             .collect::<Result<Vec<PathBuf>, std::io::Error>>()?;
         entries.sort();
         for entry in entries {
-            read(entry, text)?;
+            walk(entry, paths)?;
         }
     } else if path.is_file() && path.extension() == Some(std::ffi::OsStr::new("fan")) {
-        text.push_str(&std::fs::read_to_string(path)?)
+        paths.push(path)
     }
     Ok(())
 }
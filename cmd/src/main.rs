@@ -1,5 +1,7 @@
 mod command;
+mod server;
 use clap::{Parser, Subcommand};
+use lib::Value;
 use std::{path::PathBuf, time::Duration};
 
 #[derive(Parser)]
@@ -10,6 +12,7 @@ struct Interface {
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Evaluate codes
     Eval {
@@ -40,7 +43,81 @@ enum Commands {
         /// Stat
         #[arg(short, long)]
         stat: bool,
+        /// Compile tests to bytecode
+        #[arg(short, long)]
+        compile: bool,
+        /// Connect timeout
+        #[arg(long, value_parser = parse_duration)]
+        connect_timeout: Option<Duration>,
+        /// Read timeout
+        #[arg(long, value_parser = parse_duration)]
+        read_timeout: Option<Duration>,
+        /// Request timeout
+        #[arg(long, value_parser = parse_duration)]
+        timeout: Option<Duration>,
+        /// PEM file of extra trusted roots
+        #[arg(long)]
+        ca: Vec<PathBuf>,
+        /// PEM file of the client certificate
+        #[arg(long)]
+        cert: Option<PathBuf>,
+        /// PEM file of the client private key
+        #[arg(long)]
+        key: Option<PathBuf>,
+        /// Skip verifying server certificates
+        #[arg(long)]
+        insecure: bool,
+        /// Server name for TLS
+        #[arg(long)]
+        sni: Option<String>,
+        /// SHA-256 fingerprint the server certificate must have
+        #[arg(long)]
+        pin: Vec<String>,
+        /// Address a host resolves to, like example.com:443:127.0.0.1
+        #[arg(long)]
+        resolve: Vec<String>,
+        /// How long resolved addresses are cached
+        #[arg(long, value_parser = parse_duration)]
+        dns_ttl: Option<Duration>,
+        /// Address family to connect with, any, ipv4 or ipv6
+        #[arg(long)]
+        family: Option<String>,
+        /// Proxy to connect through, like http://proxy:3128 or socks5://proxy:1080
+        #[arg(long)]
+        proxy: Option<String>,
+        /// Hosts to connect to without a proxy, like localhost,.example.com
+        #[arg(long)]
+        no_proxy: Vec<String>,
+        /// Bytes of a response body kept in records
+        #[arg(long)]
+        max_body: Option<usize>,
+        /// Bytes a second written at most, to simulate a slow client
+        #[arg(long)]
+        upload_rate: Option<u64>,
+        /// Bytes a second read at most, to simulate a slow client
+        #[arg(long)]
+        download_rate: Option<u64>,
+        /// Latency added to every read and write
+        #[arg(long, value_parser = parse_duration)]
+        latency: Option<Duration>,
+    },
+    /// Check files
+    Check {
+        /// Path
+        #[command()]
+        path: Option<PathBuf>,
+    },
+    /// Format files
+    Fmt {
+        /// Path
+        #[command()]
+        path: Option<PathBuf>,
+        /// Check
+        #[arg(short, long)]
+        check: bool,
     },
+    /// Language server
+    Lsp,
 }
 
 fn main() {
@@ -57,12 +134,86 @@ fn main() {
             path,
             record,
             stat,
+            compile,
+            connect_timeout,
+            read_timeout,
+            timeout,
+            ca,
+            cert,
+            key,
+            insecure,
+            sni,
+            pin,
+            resolve,
+            dns_ttl,
+            family,
+            proxy,
+            no_proxy,
+            max_body,
+            upload_rate,
+            download_rate,
+            latency,
         }) => {
             let (duration, number) = match duration {
                 Some(duration) => (duration, u32::MAX),
                 None => (Duration::MAX, number),
             };
-            command::test(name, threads, duration, number, path, record, stat);
+            // timeouts and the latency are defaults of the request options, in milliseconds
+            let mut options = [
+                ("connect_timeout", connect_timeout),
+                ("read_timeout", read_timeout),
+                ("timeout", timeout),
+                ("dns_ttl", dns_ttl),
+                ("latency", latency),
+            ]
+            .into_iter()
+            .filter_map(|(name, timeout)| timeout.map(|timeout| (name.to_string(), Value::Integer(timeout.as_millis() as i64))))
+            .collect::<Vec<(String, Value)>>();
+            // so are the tls, resolver, proxy, body and throttle settings, paths are strings
+            let file = |file: PathBuf| Value::String(file.to_string_lossy().into_owned());
+            let settings = [
+                ("ca", (!ca.is_empty()).then(|| Value::Array(ca.into_iter().map(file).collect()))),
+                ("cert", cert.map(file)),
+                ("key", key.map(file)),
+                ("insecure", insecure.then_some(Value::Boolean(true))),
+                ("sni", sni.map(Value::String)),
+                (
+                    "pins",
+                    (!pin.is_empty()).then(|| Value::Array(pin.into_iter().map(Value::String).collect())),
+                ),
+                (
+                    "resolve",
+                    (!resolve.is_empty()).then(|| Value::Array(resolve.into_iter().map(Value::String).collect())),
+                ),
+                ("family", family.map(Value::String)),
+                ("max_body", max_body.map(|max| Value::Integer(max as i64))),
+                ("upload_rate", upload_rate.map(|rate| Value::Integer(rate as i64))),
+                ("download_rate", download_rate.map(|rate| Value::Integer(rate as i64))),
+                ("proxy", proxy.map(Value::String)),
+                (
+                    "no_proxy",
+                    (!no_proxy.is_empty()).then(|| Value::Array(no_proxy.into_iter().map(Value::String).collect())),
+                ),
+            ];
+            options.extend(
+                settings
+                    .into_iter()
+                    .filter_map(|(name, value)| value.map(|value| (name.to_string(), value))),
+            );
+            command::test(name, threads, duration, number, path, record, stat, compile, options);
+        }
+        Some(Commands::Check { path }) => {
+            if !command::check(path) {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Fmt { path, check }) => {
+            if !command::fmt(path, check) {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Lsp) => {
+            server::serve();
         }
         None => {
             command::repl();
@@ -71,6 +222,12 @@ fn main() {
 }
 
 fn parse_duration(s: &str) -> Result<Duration, String> {
+    if let Some(millis) = s.strip_suffix("ms") {
+        return match millis.parse::<u64>() {
+            Ok(value) => Ok(Duration::from_millis(value)),
+            Err(_) => Err(format!("Invalid number: {}", millis)),
+        };
+    }
     let mut chars = s.chars();
     let last = chars.next_back();
     let value = match chars.as_str().parse::<u64>() {
@@ -81,7 +238,7 @@ fn parse_duration(s: &str) -> Result<Duration, String> {
         Some('s') => Duration::from_secs(value),
         Some('m') => Duration::from_secs(value * 60),
         Some('h') => Duration::from_secs(value * 3600),
-        Some(c) => return Err(format!("Unknown time unit: {}, supported units: s, m, h", c)),
+        Some(c) => return Err(format!("Unknown time unit: {}, supported units: ms, s, m, h", c)),
         None => return Err("Time unit needed, for example 1s or 2m".to_string()),
     };
     Ok(duration)
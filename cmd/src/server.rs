@@ -0,0 +1,361 @@
+use crate::command;
+use lib::Kind;
+use lib::Parser;
+use lib::Source;
+use lib::Token;
+use lsp_server::Connection;
+use lsp_server::Message;
+use lsp_server::Notification;
+use lsp_server::Request;
+use lsp_server::RequestId;
+use lsp_server::Response;
+use lsp_types::notification::DidChangeTextDocument;
+use lsp_types::notification::DidCloseTextDocument;
+use lsp_types::notification::DidOpenTextDocument;
+use lsp_types::notification::Notification as _;
+use lsp_types::notification::PublishDiagnostics;
+use lsp_types::request::Completion;
+use lsp_types::request::GotoDefinition;
+use lsp_types::request::HoverRequest;
+use lsp_types::request::Request as _;
+use lsp_types::CompletionItem;
+use lsp_types::CompletionItemKind;
+use lsp_types::CompletionOptions;
+use lsp_types::CompletionParams;
+use lsp_types::Diagnostic;
+use lsp_types::DiagnosticSeverity;
+use lsp_types::DidChangeTextDocumentParams;
+use lsp_types::DidCloseTextDocumentParams;
+use lsp_types::DidOpenTextDocumentParams;
+use lsp_types::GotoDefinitionParams;
+use lsp_types::Hover;
+use lsp_types::HoverContents;
+use lsp_types::HoverParams;
+use lsp_types::HoverProviderCapability;
+use lsp_types::InitializeParams;
+use lsp_types::Location;
+use lsp_types::MarkupContent;
+use lsp_types::MarkupKind;
+use lsp_types::OneOf;
+use lsp_types::Position;
+use lsp_types::PublishDiagnosticsParams;
+use lsp_types::Range;
+use lsp_types::ServerCapabilities;
+use lsp_types::TextDocumentSyncCapability;
+use lsp_types::TextDocumentSyncKind;
+use lsp_types::Url;
+use std::collections::HashMap;
+
+/// Serves the language server protocol over stdio.
+pub fn serve() {
+    let (connection, threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![String::from(".")]),
+            ..CompletionOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    };
+    let params = match connection.initialize(serde_json::to_value(capabilities).unwrap()) {
+        Ok(params) => params,
+        Err(error) => return eprintln!("{}", error),
+    };
+    let mut server = Server::default();
+    if let Ok(InitializeParams {
+        workspace_folders: Some(folders),
+        ..
+    }) = serde_json::from_value::<InitializeParams>(params)
+    {
+        for folder in folders {
+            server.load(folder.uri);
+        }
+    }
+    if let Err(error) = server.run(&connection) {
+        eprintln!("{}", error);
+    }
+    drop(connection);
+    let _ = threads.join();
+}
+
+#[derive(Default)]
+struct Server {
+    /// Texts of the workspace files, open documents replace the files on disk
+    documents: HashMap<Url, String>,
+    /// Documents opened in the editor, which get diagnostics
+    opened: Vec<Url>,
+    /// Documents closed since diagnostics were last sent, their diagnostics are cleared
+    closed: Vec<Url>,
+}
+
+impl Server {
+    fn load(&mut self, root: Url) {
+        let mut paths = Vec::new();
+        if let Ok(path) = root.to_file_path() {
+            let _ = command::walk(path, &mut paths);
+        }
+        for path in paths {
+            if let (Ok(uri), Ok(text)) = (Url::from_file_path(&path), std::fs::read_to_string(&path)) {
+                self.documents.insert(uri, text);
+            }
+        }
+    }
+
+    fn run(&mut self, connection: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    connection.sender.send(Message::Response(self.respond(request)))?;
+                }
+                Message::Notification(notification) => {
+                    if self.notify(notification) {
+                        let closed = std::mem::take(&mut self.closed).into_iter().map(|uri| PublishDiagnosticsParams {
+                            uri,
+                            diagnostics: Vec::new(),
+                            version: None,
+                        });
+                        for params in closed.chain(self.diagnose()) {
+                            let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+                            connection.sender.send(Message::Notification(notification))?;
+                        }
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a document notification, returns whether the documents or the opened ones changed.
+    fn notify(&mut self, notification: Notification) -> bool {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => match notification.extract::<DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD) {
+                Ok(params) => {
+                    let uri = params.text_document.uri;
+                    self.documents.insert(uri.clone(), params.text_document.text);
+                    (!self.opened.contains(&uri)).then(|| self.opened.push(uri));
+                    true
+                }
+                Err(_) => false,
+            },
+            DidChangeTextDocument::METHOD => match notification.extract::<DidChangeTextDocumentParams>(DidChangeTextDocument::METHOD) {
+                Ok(mut params) => match params.content_changes.pop() {
+                    Some(change) => {
+                        self.documents.insert(params.text_document.uri, change.text);
+                        true
+                    }
+                    None => false,
+                },
+                Err(_) => false,
+            },
+            DidCloseTextDocument::METHOD => match notification.extract::<DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD) {
+                Ok(params) => {
+                    let uri = params.text_document.uri;
+                    self.opened.retain(|opened| *opened != uri);
+                    self.closed.push(uri);
+                    true
+                }
+                Err(_) => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => request
+                .extract::<GotoDefinitionParams>(GotoDefinition::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.definition(params)).unwrap()),
+            HoverRequest::METHOD => request
+                .extract::<HoverParams>(HoverRequest::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.hover(params)).unwrap()),
+            Completion::METHOD => request
+                .extract::<CompletionParams>(Completion::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.completion(params)).unwrap()),
+            _ => return Response::new_err(id, lsp_server::ErrorCode::MethodNotFound as i32, request.method),
+        };
+        match result {
+            Ok(result) => Response::new_ok(id, result),
+            Err(error) => error_response(id, error.to_string()),
+        }
+    }
+
+    /// Parses every document and checks them together, as the test command reads them all.
+    fn diagnose(&self) -> Vec<PublishDiagnosticsParams> {
+        let mut diagnostics = HashMap::<&Url, Vec<Diagnostic>>::new();
+        let mut source = Source {
+            exprs: Vec::new(),
+            requests: HashMap::new(),
+            tests: HashMap::new(),
+        };
+        let mut documents = self.documents.iter().collect::<Vec<_>>();
+        documents.sort_by_key(|(uri, _)| uri.as_str());
+        for (uri, text) in documents.iter() {
+            let mut parser = Parser::new(text);
+            match parser.parse() {
+                Ok(parsed) => {
+                    for (token, message) in command::duplicates(&parser, text, &parsed, &source) {
+                        let range = token.map(|token| range(text, &token)).unwrap_or_default();
+                        diagnostics.entry(uri).or_default().push(diagnostic(range, message));
+                    }
+                    let Source { exprs, requests, tests } = parsed;
+                    source.exprs.extend(exprs);
+                    source.requests.extend(requests);
+                    source.tests.extend(tests);
+                }
+                Err(error) => diagnostics
+                    .entry(uri)
+                    .or_default()
+                    .push(diagnostic(range(text, parser.token()), error)),
+            }
+        }
+        for problem in lib::check(&source) {
+            let located = documents
+                .iter()
+                .find_map(|(uri, text)| command::locate(text, &problem.scope, &problem.name).map(|token| (uri, range(text, &token))));
+            if let Some((uri, range)) = located {
+                diagnostics.entry(uri).or_default().push(diagnostic(range, problem.message));
+            }
+        }
+        self.opened
+            .iter()
+            .map(|uri| PublishDiagnosticsParams {
+                uri: uri.clone(),
+                diagnostics: diagnostics.remove(uri).unwrap_or_default(),
+                version: None,
+            })
+            .collect()
+    }
+
+    fn source(&self) -> Source {
+        let mut source = Source {
+            exprs: Vec::new(),
+            requests: HashMap::new(),
+            tests: HashMap::new(),
+        };
+        for text in self.documents.values() {
+            if let Ok(Source { exprs, requests, tests }) = Parser::new(text).parse() {
+                source.exprs.extend(exprs);
+                source.requests.extend(requests);
+                source.tests.extend(tests);
+            }
+        }
+        source
+    }
+
+    /// Finds the ident under the cursor.
+    fn ident(&self, uri: &Url, position: Position) -> Option<Token> {
+        let text = self.documents.get(uri)?;
+        lib::segment(text)
+            .into_iter()
+            .find(|token| token.kind == Kind::Ident && contains(&range(text, token), position))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<Location> {
+        let position = params.text_document_position_params;
+        let ident = self.ident(&position.text_document.uri, position.position)?;
+        self.documents.iter().find_map(|(uri, text)| {
+            let tokens = lib::segment(text);
+            tokens
+                .windows(2)
+                .find(|pair| matches!(pair[0].kind, Kind::Request | Kind::Test) && pair[1].literal == ident.literal)
+                .map(|pair| Location::new(uri.clone(), range(text, &pair[1])))
+        })
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let uri = &position.text_document.uri;
+        let ident = self.ident(uri, position.position)?;
+        let message = lib::render(&self.source(), &ident.literal)?;
+        let lines = message.trim().lines().map(|line| line.trim()).collect::<Vec<&str>>();
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("request {}\n```http\n{}\n```", ident.literal, lines.join("\n")),
+            }),
+            range: self.documents.get(uri).map(|text| range(text, &ident)),
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Vec<CompletionItem> {
+        let position = params.text_document_position;
+        let field = self
+            .documents
+            .get(&position.text_document.uri)
+            .and_then(|text| text.lines().nth(position.position.line as usize))
+            .and_then(|line| line.chars().take(chars(line, position.position.character)).last())
+            == Some('.');
+        let item = |label: &str, kind, detail: &str| CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            detail: Some(detail.to_string()),
+            ..CompletionItem::default()
+        };
+        let mut items = lib::FIELDS
+            .iter()
+            .map(|name| item(name, CompletionItemKind::FIELD, "response field"))
+            .collect::<Vec<CompletionItem>>();
+        if !field {
+            items.extend(
+                lib::NATIVES
+                    .iter()
+                    .map(|name| item(name, CompletionItemKind::FUNCTION, "native function")),
+            );
+            let mut requests = self.source().requests.into_keys().collect::<Vec<String>>();
+            requests.sort();
+            items.extend(requests.iter().map(|name| item(name, CompletionItemKind::FUNCTION, "request")));
+        }
+        items
+    }
+}
+
+fn error_response(id: RequestId, message: String) -> Response {
+    Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, message)
+}
+
+fn diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some(String::from("basjoofan")),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// The range of a token on its first line, positions count from zero in UTF-16 code units like the protocol does.
+fn range(text: &str, token: &Token) -> Range {
+    let line = token.line.saturating_sub(1);
+    let before = text
+        .lines()
+        .nth(line)
+        .unwrap_or_default()
+        .chars()
+        .take(token.column.saturating_sub(1));
+    let start = before.map(char::len_utf16).sum::<usize>() as u32;
+    let first = token.literal.lines().next().unwrap_or_default();
+    let length = first.chars().map(char::len_utf16).sum::<usize>().max(1) as u32;
+    Range::new(Position::new(line as u32, start), Position::new(line as u32, start + length))
+}
+
+/// The chars of a line before a position counted in UTF-16 code units.
+fn chars(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    line.chars()
+        .take_while(|char| {
+            units += char.len_utf16();
+            units <= character as usize
+        })
+        .count()
+}
+
+fn contains(range: &Range, position: Position) -> bool {
+    range.start <= position && position <= range.end
+}
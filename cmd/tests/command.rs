@@ -74,3 +74,119 @@ fn test_command_test() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_command_fmt() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let file = temp.child(format!("request.{}", "fan"));
+    file.write_str("let host=\"httpbin.org\"\ntest call {\n// call\nlet response=get();response.status\n}\n")?;
+    let formatted = "let host = \"httpbin.org\";\n\ntest call {\n    // call\n    let response = get();\n    response.status\n}\n";
+    // command fmt check
+    let mut command = Command::cargo_bin("basjoofan")?;
+    command.current_dir(&temp);
+    command.arg("fmt").arg("--check");
+    command.assert().failure().stdout(predicate::str::contains("Unformatted: "));
+    // command fmt
+    let mut command = Command::cargo_bin("basjoofan")?;
+    command.current_dir(&temp);
+    command.arg("fmt");
+    command.assert().success();
+    file.assert(formatted);
+    // command fmt check formatted
+    let mut command = Command::cargo_bin("basjoofan")?;
+    command.current_dir(&temp);
+    command.arg("fmt").arg("--check");
+    command.assert().success().stdout(predicate::str::is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_command_check() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let file = temp.child(format!("request.{}", "fan"));
+    let text = r#"
+    let host = "httpbin.org";
+    request get`
+        GET http://{hots}/get
+    `[status == 200];
+    test call {
+        let response = gte();
+        response.status
+    }
+    "#;
+    file.write_str(text)?;
+    let mut command = Command::cargo_bin("basjoofan")?;
+    command.current_dir(&temp);
+    command.arg("check");
+    let path = file.path().display();
+    command.assert().failure().stdout(predicate::str::diff(format!(
+        "{path}:7:24: test call: function gte not found\n{path}:3:13: request get: request get is never called\n"
+    )));
+    file.write_str(&text.replace("gte", "get").replace("hots", "host"))?;
+    let mut command = Command::cargo_bin("basjoofan")?;
+    command.current_dir(&temp);
+    command.arg("check");
+    command.assert().success().stdout(predicate::str::is_empty());
+    // a request or test defined again, in the same file or another one
+    let other = temp.child(format!("other.{}", "fan"));
+    other.write_str("test call {}\ntest other { get() }\n  test other {}\n")?;
+    let mut command = Command::cargo_bin("basjoofan")?;
+    command.current_dir(&temp);
+    command.arg("check");
+    let other = other.path().display();
+    command.assert().failure().stdout(predicate::str::diff(format!(
+        "{other}:3:8: test other is defined more than once\n{path}:6:10: test call is defined more than once\n"
+    )));
+    Ok(())
+}
+
+#[test]
+#[allow(clippy::zombie_processes)]
+fn test_command_lsp() -> Result<(), Box<dyn std::error::Error>> {
+    let text =
+        "let host = \"httpbin.org\";\nrequest get`\n    GET http://{host}/get\n`;\ntest call {\n    get();\n    \"🎵\"; post();\n}\n";
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#.to_string(),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"file:///call.fan","languageId":"fan","version":1,"text":{}}}}}}}"#,
+            serde_json::to_string(text)?
+        ),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"file:///other.fan","languageId":"fan","version":1,"text":{}}}}}}}"#,
+            serde_json::to_string("test call {\n    post();\n}\n")?
+        ),
+        r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///call.fan"},"position":{"line":5,"character":5}}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/definition","params":{"textDocument":{"uri":"file:///call.fan"},"position":{"line":5,"character":5}}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"textDocument/didClose","params":{"textDocument":{"uri":"file:///call.fan"}}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string(),
+    ];
+    let mut child = Command::cargo_bin("basjoofan")?
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn child process");
+    if let Some(mut stdin) = child.stdin.take() {
+        for message in messages {
+            write!(stdin, "Content-Length: {}\r\n\r\n{}", message.len(), message).expect("Failed to write to stdin");
+        }
+    }
+    let output = child.wait_with_output().expect("Failed to read stdout");
+    let output = String::from_utf8_lossy(&output.stdout);
+    println!("output:{}", output);
+    assert!(output.contains(r#""message":"function post not found""#));
+    // the string before the call holds a char of two UTF-16 code units
+    assert!(output.contains(r#""range":{"end":{"character":14,"line":6},"start":{"character":10,"line":6}}"#));
+    assert!(output.contains(r#"GET http://httpbin.org/get"#));
+    assert!(output.contains(r#""range":{"end":{"character":11,"line":1},"start":{"character":8,"line":1}},"uri":"file:///call.fan""#));
+    // a test defined again in another document is reported where it is defined again
+    assert!(output.contains(r#"{"diagnostics":[{"message":"test call is defined more than once","range":{"end":{"character":9,"line":0},"start":{"character":5,"line":0}},"severity":1,"source":"basjoofan"}],"uri":"file:///other.fan"}"#));
+    // a closed document is left without diagnostics
+    let last = output
+        .rsplit("publishDiagnostics")
+        .find(|params| params.contains(r#""uri":"file:///call.fan""#));
+    assert!(last.is_some_and(|params| params.starts_with(r#"","params":{"diagnostics":[],"#)));
+    Ok(())
+}
@@ -0,0 +1,233 @@
+use crate::evaluator::placeholders;
+use crate::evaluator::OPTIONS;
+use crate::http;
+use crate::native;
+use crate::parser::Declaration;
+use crate::Expr;
+use crate::Source;
+use crate::Value;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result;
+
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    /// Where the problem is, for example `test call`
+    pub scope: String,
+    /// The name that could not be resolved
+    pub name: String,
+    /// What is wrong with the name
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}: {}", self.scope, self.message)
+    }
+}
+
+/// Resolves identifiers, calls and template placeholders without evaluating the source.
+pub fn check(source: &Source) -> Vec<Problem> {
+    let mut checker = Checker {
+        requests: &source.requests,
+        problems: Vec::new(),
+        used: HashSet::new(),
+    };
+    let mut globals = HashSet::new();
+    checker.block(&source.exprs, &mut globals, "source");
+    let mut tests = source.tests.iter().collect::<Vec<_>>();
+    tests.sort_by_key(|(name, _)| name.as_str());
+    for (name, exprs) in tests {
+        checker.block(exprs, &mut globals.clone(), &format!("test {}", name));
+    }
+    let mut requests = source.requests.iter().collect::<Vec<_>>();
+    requests.sort_by_key(|(name, _)| name.as_str());
+    for (name, (_, asserts, options)) in requests {
+        let scope = format!("request {}", name);
+        for (option, _) in options {
+            if !OPTIONS.contains(&option.as_str()) {
+                checker.report(&scope, option, format!("option {} not found", option));
+            }
+        }
+        let mut fields = http::FIELDS.iter().map(|field| field.to_string()).collect::<HashSet<String>>();
+        for assert in asserts {
+            checker.expr(assert, &mut fields, &scope);
+        }
+        if !checker.used.contains(name.as_str()) {
+            checker.report(&scope, name, format!("request {} is never called", name));
+        }
+    }
+    checker.problems
+}
+
+/// Renders a request template with the literal values of the source's variables,
+/// placeholders that are only known at run time are kept.
+pub fn render(source: &Source, name: &str) -> Option<String> {
+    let (message, _, _) = source.requests.get(name)?;
+    let mut values = HashMap::new();
+    for expr in source.exprs.iter() {
+        if let Expr::Let(name, value) = expr {
+            match value.eval() {
+                Value::Null => values.remove(name),
+                value => values.insert(name.to_owned(), value),
+            };
+        }
+    }
+    let ranges = placeholders(message)
+        .into_iter()
+        .filter_map(|(name, range)| values.get(name).map(|value| (value.to_string(), range)))
+        .rev()
+        .collect::<Vec<_>>();
+    let mut message = message.to_owned();
+    for (value, range) in ranges {
+        message.replace_range(range, &value);
+    }
+    Some(message)
+}
+
+struct Checker<'a> {
+    requests: &'a HashMap<String, Declaration>,
+    problems: Vec<Problem>,
+    used: HashSet<&'a str>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, scope: &str, name: &str, message: String) {
+        let problem = Problem {
+            scope: scope.to_string(),
+            name: name.to_string(),
+            message,
+        };
+        if !self.problems.contains(&problem) {
+            self.problems.push(problem);
+        }
+    }
+
+    fn block(&mut self, exprs: &[Expr], names: &mut HashSet<String>, scope: &str) {
+        for expr in exprs {
+            self.expr(expr, names, scope);
+        }
+    }
+
+    fn template(&mut self, template: &str, names: &HashSet<String>, scope: &str) {
+        for (name, _) in placeholders(template) {
+            if !names.contains(name) {
+                self.report(scope, name, format!("variable {{{}}} not found", name));
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, names: &mut HashSet<String>, scope: &str) {
+        match expr {
+            Expr::Template(template) => self.template(template, names, scope),
+            Expr::Array(items) => self.block(items, names, scope),
+            Expr::Map(pairs) => {
+                for (key, value) in pairs {
+                    self.expr(key, names, scope);
+                    self.expr(value, names, scope);
+                }
+            }
+            Expr::Index(left, index) => {
+                self.expr(left, names, scope);
+                self.expr(index, names, scope);
+            }
+            Expr::Field(left, _) => self.expr(left, names, scope),
+            Expr::Ident(ident) => {
+                if !names.contains(ident) {
+                    self.report(scope, ident, format!("ident:{} not found", ident));
+                }
+            }
+            Expr::Let(name, value) => {
+                self.expr(value, names, scope);
+                names.insert(name.to_owned());
+            }
+            Expr::Unary(_, right) => self.expr(right, names, scope),
+            Expr::Binary(_, left, right) => {
+                self.expr(left, names, scope);
+                self.expr(right, names, scope);
+            }
+            Expr::Paren(value) => self.expr(value, names, scope),
+            Expr::If(condition, consequence, alternative) => {
+                self.expr(condition, names, scope);
+                // variables are not scoped to blocks, so either branch may define them
+                self.block(consequence, names, scope);
+                self.block(alternative, names, scope);
+            }
+            Expr::Call(function, arguments) => {
+                self.block(arguments, names, scope);
+                match self.requests.get_key_value(function) {
+                    Some((name, (message, _, options))) => {
+                        self.used.insert(name.as_str());
+                        // options are evaluated where the request is called
+                        for (_, value) in options {
+                            self.expr(value, names, scope);
+                        }
+                        self.template(message, names, scope);
+                    }
+                    None if native::NAMES.contains(&function.as_str()) => {}
+                    None => self.report(scope, function, format!("function {} not found", function)),
+                }
+            }
+            Expr::Integer(_) | Expr::Float(_) | Expr::Boolean(_) | Expr::String(_) | Expr::Raw(..) | Expr::Comment(..) => {}
+        }
+    }
+}
+
+#[test]
+fn test_check_source() {
+    let text = r#"
+    let host = "httpbin.org";
+    request get`
+        GET http://{host}/{path}
+    `[status == 200, stauts == 200];
+    request unused(redirects: limit, follow: true)`
+        GET http://{host}/
+    `;
+    test call {
+        let response = get();
+        println("{}", respones.status);
+        let body = `{"id": {id}}`;
+        post();
+    }
+    test defined {
+        let path = "get";
+        if (true) { let id = 1; }
+        get();
+        `{id}`
+    }
+    "#;
+    let source = crate::Parser::new(text).parse().unwrap();
+    let problems = check(&source).iter().map(|problem| problem.to_string()).collect::<Vec<String>>();
+    assert_eq!(
+        problems,
+        vec![
+            "test call: variable {path} not found",
+            "test call: ident:respones not found",
+            "test call: variable {id} not found",
+            "test call: function post not found",
+            "request get: ident:stauts not found",
+            "request unused: option follow not found",
+            "request unused: request unused is never called",
+        ]
+    );
+}
+
+#[test]
+fn test_render_request() {
+    let text = r#"
+    let host = "httpbin.org";
+    let port = 80;
+    let path = format("{}", "get");
+    request get`
+        GET http://{host}:{port}/{path}
+    `;
+    "#;
+    let source = crate::Parser::new(text).parse().unwrap();
+    assert_eq!(
+        render(&source, "get"),
+        Some(String::from("\n        GET http://httpbin.org:80/{path}\n    "))
+    );
+    assert_eq!(render(&source, "post"), None);
+}
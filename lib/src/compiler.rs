@@ -0,0 +1,353 @@
+use crate::evaluator::placeholders;
+use crate::native;
+use crate::native::Native;
+use crate::native::Stateful;
+use crate::Context;
+use crate::Expr;
+use crate::Kind;
+use crate::Token;
+use crate::Value;
+
+/// Bytecode compiled from a block of expressions, run by a `Machine`.
+pub struct Program {
+    pub(crate) code: Vec<Op>,
+    /// Variable names, indexed by slot
+    pub(crate) names: Vec<String>,
+    pub(crate) requests: Vec<Request>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Op {
+    Push(Value),
+    Pop,
+    Load(usize),
+    /// Loads a field or constant index path of a variable without cloning the values along it
+    LoadPath(usize, Vec<Step>),
+    Store(usize),
+    Template(Vec<Segment>),
+    Array(usize),
+    Map(usize),
+    Index,
+    Field(String),
+    Unary(Token),
+    Binary(Token),
+    /// Jumps when the value on the stack is truthy, otherwise pops it
+    JumpIfTrue(usize),
+    /// Jumps when the value on the stack is falsy, otherwise pops it
+    JumpIfFalse(usize),
+    /// Pops the condition and jumps when it is falsy
+    JumpUnless(usize),
+    Jump(usize),
+    Native(Native, usize),
+    Stateful(Stateful, usize),
+    Request(usize, usize),
+    Call(String),
+    Fail(String),
+}
+
+#[derive(Debug)]
+pub(crate) enum Step {
+    Field(String),
+    Index(Value),
+}
+
+#[derive(Debug)]
+pub(crate) enum Segment {
+    Static(String),
+    Slot(usize),
+}
+
+pub(crate) struct Request {
+    pub(crate) name: String,
+    pub(crate) message: Vec<Segment>,
+    /// Names of the options, their values are pushed by the caller after the arguments
+    pub(crate) options: Vec<String>,
+    /// Names of the response fields the asserts use, indexed by slot
+    pub(crate) names: Vec<String>,
+    pub(crate) asserts: Vec<Assert>,
+}
+
+pub(crate) struct Assert {
+    pub(crate) expr: String,
+    pub(crate) token: Token,
+    pub(crate) left: Vec<Op>,
+    pub(crate) right: Vec<Op>,
+}
+
+/// Compiles a block of expressions, calls resolve against the requests of the context.
+pub fn compile(exprs: &[Expr], context: &Context) -> Program {
+    let mut compiler = Compiler {
+        code: Vec::new(),
+        names: Vec::new(),
+        requests: Vec::new(),
+        context: Some(context),
+    };
+    compiler.block(exprs);
+    Program {
+        code: compiler.code,
+        names: compiler.names,
+        requests: compiler.requests,
+    }
+}
+
+struct Compiler<'a> {
+    code: Vec<Op>,
+    names: Vec<String>,
+    requests: Vec<Request>,
+    /// Asserts are compiled without a context, they can not call requests
+    context: Option<&'a Context>,
+}
+
+impl Compiler<'_> {
+    fn slot(&mut self, name: &str) -> usize {
+        match self.names.iter().position(|n| n == name) {
+            Some(slot) => slot,
+            None => {
+                self.names.push(name.to_owned());
+                self.names.len() - 1
+            }
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    /// Points a jump emitted before at the next instruction.
+    fn patch(&mut self, jump: usize) {
+        let target = self.code.len();
+        match &mut self.code[jump] {
+            Op::JumpIfTrue(to) | Op::JumpIfFalse(to) | Op::JumpUnless(to) | Op::Jump(to) => *to = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn block(&mut self, exprs: &[Expr]) {
+        if exprs.is_empty() {
+            self.emit(Op::Push(Value::Null));
+        }
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.emit(Op::Pop);
+            }
+            self.expr(expr);
+        }
+    }
+
+    fn template(&mut self, template: &str) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for (name, range) in placeholders(template) {
+            if range.start > start {
+                segments.push(Segment::Static(template[start..range.start].to_owned()));
+            }
+            segments.push(Segment::Slot(self.slot(name)));
+            start = range.end;
+        }
+        if start < template.len() {
+            segments.push(Segment::Static(template[start..].to_owned()));
+        }
+        segments
+    }
+
+    /// Collects a variable followed by fields and literal indexes, like `response.json.items[0]`.
+    fn path(expr: &Expr, steps: &mut Vec<Step>) -> Option<String> {
+        match expr {
+            Expr::Ident(ident) => Some(ident.to_owned()),
+            Expr::Field(left, field) => {
+                let ident = Self::path(left, steps)?;
+                steps.push(Step::Field(field.to_owned()));
+                Some(ident)
+            }
+            Expr::Index(left, index) => {
+                let index = match index.as_ref() {
+                    Expr::Integer(integer) => Value::Integer(*integer),
+                    Expr::String(string) | Expr::Raw(string, _) => Value::String(string.to_owned()),
+                    _ => return None,
+                };
+                let ident = Self::path(left, steps)?;
+                steps.push(Step::Index(index));
+                Some(ident)
+            }
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Integer(integer) => {
+                self.emit(Op::Push(Value::Integer(*integer)));
+            }
+            Expr::Float(float) => {
+                self.emit(Op::Push(Value::Float(*float)));
+            }
+            Expr::Boolean(boolean) => {
+                self.emit(Op::Push(Value::Boolean(*boolean)));
+            }
+            Expr::String(string) | Expr::Raw(string, _) => {
+                self.emit(Op::Push(Value::String(string.to_owned())));
+            }
+            Expr::Template(template) => {
+                let segments = self.template(template);
+                self.emit(Op::Template(segments));
+            }
+            Expr::Array(items) => {
+                items.iter().for_each(|item| self.expr(item));
+                self.emit(Op::Array(items.len()));
+            }
+            Expr::Map(pairs) => {
+                for (key, value) in pairs {
+                    self.expr(key);
+                    self.expr(value);
+                }
+                self.emit(Op::Map(pairs.len()));
+            }
+            Expr::Index(left, index) => {
+                let mut steps = Vec::new();
+                match Self::path(expr, &mut steps) {
+                    Some(ident) => {
+                        let slot = self.slot(&ident);
+                        self.emit(Op::LoadPath(slot, steps));
+                    }
+                    None => {
+                        self.expr(left);
+                        self.expr(index);
+                        self.emit(Op::Index);
+                    }
+                }
+            }
+            Expr::Field(left, field) => {
+                let mut steps = Vec::new();
+                match Self::path(expr, &mut steps) {
+                    Some(ident) => {
+                        let slot = self.slot(&ident);
+                        self.emit(Op::LoadPath(slot, steps));
+                    }
+                    None => {
+                        self.expr(left);
+                        self.emit(Op::Field(field.to_owned()));
+                    }
+                }
+            }
+            Expr::Ident(ident) => {
+                let slot = self.slot(ident);
+                self.emit(Op::Load(slot));
+            }
+            Expr::Let(name, value) => {
+                self.expr(value);
+                let slot = self.slot(name);
+                self.emit(Op::Store(slot));
+            }
+            Expr::Unary(token, right) => {
+                self.expr(right);
+                self.emit(Op::Unary(token.to_owned()));
+            }
+            Expr::Binary(token, left, right) => match token.kind {
+                Kind::Lo | Kind::La => {
+                    self.expr(left);
+                    let jump = match token.kind {
+                        Kind::Lo => self.emit(Op::JumpIfTrue(usize::MAX)),
+                        _ => self.emit(Op::JumpIfFalse(usize::MAX)),
+                    };
+                    self.expr(right);
+                    self.patch(jump);
+                }
+                Kind::Add
+                | Kind::Sub
+                | Kind::Mul
+                | Kind::Div
+                | Kind::Rem
+                | Kind::Bx
+                | Kind::Bo
+                | Kind::Ba
+                | Kind::Sl
+                | Kind::Sr
+                | Kind::Lt
+                | Kind::Gt
+                | Kind::Le
+                | Kind::Ge
+                | Kind::Eq
+                | Kind::Ne => {
+                    self.expr(left);
+                    self.expr(right);
+                    self.emit(Op::Binary(token.to_owned()));
+                }
+                _ => {
+                    self.emit(Op::Fail(format!("not support operator: {} {} {}", left, token, right)));
+                }
+            },
+            Expr::Paren(value) => self.expr(value),
+            Expr::If(condition, consequence, alternative) => {
+                self.expr(condition);
+                let otherwise = self.emit(Op::JumpUnless(usize::MAX));
+                self.block(consequence);
+                let end = self.emit(Op::Jump(usize::MAX));
+                self.patch(otherwise);
+                self.block(alternative);
+                self.patch(end);
+            }
+            Expr::Call(name, arguments) => {
+                arguments.iter().for_each(|argument| self.expr(argument));
+                let op = match self.context.and_then(|context| context.request(name)) {
+                    Some(declaration) => {
+                        let (message, asserts, options) = declaration.as_ref();
+                        options.iter().for_each(|(_, value)| self.expr(value));
+                        Op::Request(self.request(name, message, asserts, options), arguments.len())
+                    }
+                    None => match (native::function(name), native::stateful(name)) {
+                        (Some(function), _) => Op::Native(function, arguments.len()),
+                        (None, Some(function)) => Op::Stateful(function, arguments.len()),
+                        (None, None) => Op::Call(name.to_owned()),
+                    },
+                };
+                self.emit(op);
+            }
+            Expr::Comment(..) => {
+                self.emit(Op::Push(Value::Null));
+            }
+        }
+    }
+
+    /// Compiles a request once per program, its placeholders read the slots of the caller.
+    fn request(&mut self, name: &str, message: &str, asserts: &[Expr], options: &[(String, Expr)]) -> usize {
+        if let Some(index) = self.requests.iter().position(|request| request.name == name) {
+            return index;
+        }
+        let message = self.template(message);
+        let mut compiler = Compiler {
+            code: Vec::new(),
+            names: Vec::new(),
+            requests: Vec::new(),
+            context: None,
+        };
+        let asserts = asserts
+            .iter()
+            .filter_map(|assert| match assert {
+                Expr::Binary(token, left, right)
+                    if matches!(token.kind, Kind::Lt | Kind::Gt | Kind::Le | Kind::Ge | Kind::Eq | Kind::Ne) =>
+                {
+                    compiler.expr(left);
+                    let left_code = std::mem::take(&mut compiler.code);
+                    compiler.expr(right);
+                    let right_code = std::mem::take(&mut compiler.code);
+                    Some(Assert {
+                        expr: format!("{} {} {}", left, token, right),
+                        token: token.to_owned(),
+                        left: left_code,
+                        right: right_code,
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        self.requests.push(Request {
+            name: name.to_owned(),
+            message,
+            options: options.iter().map(|(option, _)| option.to_owned()).collect(),
+            names: compiler.names,
+            asserts,
+        });
+        self.requests.len() - 1
+    }
+}
@@ -1,18 +1,31 @@
+use crate::http::Fault;
+use crate::http::Jar;
+use crate::http::Proxies;
 use crate::http::Request;
 use crate::http::Response;
+use crate::http::Socket;
 use crate::http::Time;
-use crate::Expr;
+use crate::parser::Declaration;
 use crate::Value;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct Context {
     inner: HashMap<String, Value>,
-    requests: HashMap<String, (String, Vec<Expr>)>,
+    requests: HashMap<String, Arc<Declaration>>,
     records: Vec<Record>,
+    /// Defaults of the request options, like timeouts given on the command line
+    options: Vec<(String, Value)>,
+    /// Cookies of the virtual user, kept between the requests it sends
+    jar: Jar,
+    /// The websocket the virtual user opened last, with the name of the request that opened it
+    socket: Option<(String, Socket)>,
+    /// Proxies of the environment, read once for every request of the run
+    proxies: Proxies,
 }
 
 impl Context {
@@ -21,6 +34,10 @@ impl Context {
             inner: HashMap::new(),
             requests: HashMap::new(),
             records: Vec::new(),
+            options: Vec::new(),
+            jar: Jar::default(),
+            socket: None,
+            proxies: Proxies::from_env(),
         }
     }
 
@@ -29,11 +46,16 @@ impl Context {
             inner,
             requests: HashMap::new(),
             records: Vec::new(),
+            options: Vec::new(),
+            jar: Jar::default(),
+            socket: None,
+            proxies: Proxies::default(),
         }
     }
 
-    pub fn extend(&mut self, requests: HashMap<String, (String, Vec<Expr>)>) {
-        self.requests.extend(requests);
+    pub fn extend(&mut self, requests: HashMap<String, Declaration>) {
+        self.requests
+            .extend(requests.into_iter().map(|(name, declaration)| (name, Arc::new(declaration))));
     }
 
     pub fn get(&self, key: &str) -> Option<&Value> {
@@ -44,8 +66,40 @@ impl Context {
         self.inner.insert(key, value);
     }
 
-    pub fn request(&self, name: &str) -> Option<&(String, Vec<Expr>)> {
-        self.requests.get(name)
+    /// The declaration of a request, shared so calling it does not copy it.
+    pub fn request(&self, name: &str) -> Option<Arc<Declaration>> {
+        self.requests.get(name).cloned()
+    }
+
+    /// Sets defaults of the request options, the options a request declares override them.
+    pub fn configure(&mut self, options: Vec<(String, Value)>) {
+        self.options.extend(options);
+    }
+
+    pub(crate) fn options(&self) -> &[(String, Value)] {
+        &self.options
+    }
+
+    pub(crate) fn proxies(&self) -> &Proxies {
+        &self.proxies
+    }
+
+    pub(crate) fn jar(&mut self) -> &mut Jar {
+        &mut self.jar
+    }
+
+    /// Keeps the websocket a request opened, dropping the one opened before.
+    pub(crate) fn open(&mut self, name: String, socket: Socket) {
+        self.socket = Some((name, socket));
+    }
+
+    pub(crate) fn socket(&mut self) -> Option<&mut (String, Socket)> {
+        self.socket.as_mut()
+    }
+
+    /// Forgets the websocket once it is closed.
+    pub(crate) fn forget(&mut self) {
+        self.socket.take_if(|(_, socket)| socket.closed());
     }
 
     pub fn push(&mut self, record: Record) {
@@ -67,6 +121,10 @@ impl Clone for Context {
             inner: self.inner.clone(),
             requests: self.requests.clone(),
             records: Vec::new(),
+            options: self.options.clone(),
+            jar: self.jar.clone(),
+            socket: None,
+            proxies: self.proxies.clone(),
         }
     }
 }
@@ -78,6 +136,10 @@ pub struct Record {
     pub response: Response,
     pub asserts: Vec<Assert>,
     pub error: String,
+    /// Kind of the error, timeouts are told apart from other failures
+    pub kind: Fault,
+    /// Which send of the request this is, from 1, the attempts that were retried are recorded too
+    pub attempt: u32,
 }
 
 pub struct Assert {
@@ -110,14 +172,19 @@ impl Display for Record {
                 assert.expr, assert.left, assert.compare, assert.right, assert.result
             )?
         }
+        let attempt = match self.attempt {
+            0 | 1 => String::new(),
+            attempt => format!(" #{}", attempt),
+        };
         write!(
             f,
-            "--- {}  {} ({:?})",
+            "--- {}  {}{} ({:?})",
             match flag {
                 true => "PASS",
                 false => "FAIL",
             },
             self.name,
+            attempt,
             self.time.total
         )
     }
@@ -1,4 +1,5 @@
 use crate::http;
+use crate::http::Fault;
 use crate::native;
 use crate::Assert;
 use crate::Context;
@@ -8,13 +9,18 @@ use crate::Record;
 use crate::Token;
 use crate::Value;
 use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 fn eval_expr(expr: &Expr, context: &mut Context) -> Result<Value, String> {
     match expr {
         Expr::Integer(integer) => eval_integer_literal(integer),
         Expr::Float(float) => eval_float_literal(float),
         Expr::Boolean(boolean) => eval_boolean_literal(boolean),
-        Expr::String(string) => eval_string_literal(string),
+        Expr::String(string) | Expr::Raw(string, _) => eval_string_literal(string),
+        Expr::Template(template) => eval_template_literal(template, context),
         Expr::Array(items) => eval_array_literal(items, context),
         Expr::Map(pairs) => eval_map_literal(pairs, context),
         Expr::Index(value, index) => eval_index_expr(value, index, context),
@@ -26,6 +32,7 @@ fn eval_expr(expr: &Expr, context: &mut Context) -> Result<Value, String> {
         Expr::Paren(expr) => eval_expr(expr, context),
         Expr::If(condition, consequence, alternative) => eval_if_expr(condition, consequence, alternative, context),
         Expr::Call(name, arguments) => eval_call_expr(name, arguments, context),
+        Expr::Comment(..) => Ok(Value::Null),
     }
 }
 
@@ -45,6 +52,10 @@ fn eval_string_literal(string: &String) -> Result<Value, String> {
     Ok(Value::String(string.to_owned()))
 }
 
+fn eval_template_literal(template: &str, context: &mut Context) -> Result<Value, String> {
+    Ok(Value::String(interpolate(template, context, false)))
+}
+
 fn eval_array_literal(items: &[Expr], context: &mut Context) -> Result<Value, String> {
     Ok(Value::Array(eval_list(items, context)?))
 }
@@ -164,73 +175,276 @@ fn eval_if_expr(condition: &Expr, consequence: &[Expr], alternative: &[Expr], co
 fn eval_call_expr(name: &str, arguments: &[Expr], context: &mut Context) -> Result<Value, String> {
     let arguments = eval_list(arguments, context)?;
     match context.request(name) {
-        Some((message, asserts)) => {
-            let name = name.to_string();
-            let regex = regex::Regex::new(r"\{\s*([a-zA-Z_][a-zA-Z0-9_]*)\s*\}").unwrap();
-            let matches = regex.find_iter(message);
-            let mut ranges = Vec::new();
-            matches.for_each(|m| ranges.push((m.as_str()[1..m.as_str().len() - 1].trim(), m.range())));
-            ranges.reverse();
-            let mut message = message.to_string();
-            for (variable, range) in ranges.into_iter() {
-                let variable = match context.get(variable) {
-                    Some(variable) => variable.to_string(),
-                    None => Value::Null.to_string(),
-                };
-                message.replace_range(range, variable.as_str());
+        Some(declaration) => {
+            let (message, asserts, options) = declaration.as_ref();
+            let mut values = Vec::new();
+            for (option, value) in options.iter() {
+                values.push((option.to_owned(), eval_expr(value, context)?));
             }
-            let client = http::Client::default();
-            let (request, response, time, error) = client.send(message.as_str());
-            let map = response.to_map();
-            let mut local = Context::from(map);
-            let asserts = asserts
-                .iter()
-                .filter_map(|assert| match assert {
-                    Expr::Binary(token, left, right) => {
-                        let expr = format!("{} {} {}", left, token, right);
-                        let left = eval_expr(left, &mut local).unwrap_or(Value::Null);
-                        let right = eval_expr(right, &mut local).unwrap_or(Value::Null);
-                        match token.kind {
-                            Kind::Lt => Some(left < right),
-                            Kind::Gt => Some(left > right),
-                            Kind::Le => Some(left <= right),
-                            Kind::Ge => Some(left >= right),
-                            Kind::Eq => Some(left == right),
-                            Kind::Ne => Some(left != right),
-                            _ => None,
+            let message = interpolate(message, context, true);
+            call(name, &message, values, context, |map| {
+                let mut local = Context::from(std::mem::take(map));
+                let asserts = asserts
+                    .iter()
+                    .filter_map(|assert| match assert {
+                        Expr::Binary(token, left, right) => {
+                            let expr = format!("{} {} {}", left, token, right);
+                            let left = eval_expr(left, &mut local).unwrap_or(Value::Null);
+                            let right = eval_expr(right, &mut local).unwrap_or(Value::Null);
+                            match token.kind {
+                                Kind::Lt => Some(left < right),
+                                Kind::Gt => Some(left > right),
+                                Kind::Le => Some(left <= right),
+                                Kind::Ge => Some(left >= right),
+                                Kind::Eq => Some(left == right),
+                                Kind::Ne => Some(left != right),
+                                _ => None,
+                            }
+                            .map(|result| Assert {
+                                expr,
+                                left: left.to_string(),
+                                compare: token.to_string(),
+                                right: right.to_string(),
+                                result,
+                            })
                         }
-                        .map(|result| Assert {
-                            expr,
-                            left: left.to_string(),
-                            compare: token.to_string(),
-                            right: right.to_string(),
-                            result,
-                        })
-                    }
-                    _ => None,
-                })
-                .collect::<Vec<Assert>>();
-            context.push(Record {
-                name,
-                request,
-                response,
-                time,
-                error,
-                asserts,
-            });
-            Ok(Value::Map(local.into_map()))
+                        _ => None,
+                    })
+                    .collect::<Vec<Assert>>();
+                *map = local.into_map();
+                asserts
+            })
         }
-        None => match name {
-            "println" => Ok(native::println(arguments)?),
-            "print" => Ok(native::print(arguments)?),
-            "format" => Ok(native::format(arguments)?),
-            "length" => Ok(native::length(arguments)?),
-            "append" => Ok(native::append(arguments)?),
-            _ => Err(format!("function {} not found", name)),
+        None => match (native::function(name), native::stateful(name)) {
+            (Some(function), _) => function(arguments),
+            (None, Some(function)) => function(arguments, context),
+            (None, None) => Err(format!("function {} not found", name)),
         },
     }
 }
 
+/// Sends a declared request with its interpolated message and the values of its options, retrying it as they say.
+/// Every attempt is recorded with the asserts checked against its response, the fields of the last one are returned.
+pub(crate) fn call<F>(
+    name: &str,
+    message: &str,
+    options: Vec<(String, Value)>,
+    context: &mut Context,
+    mut check: F,
+) -> Result<Value, String>
+where
+    F: FnMut(&mut HashMap<String, Value>) -> Vec<Assert>,
+{
+    // options of the request come after the defaults of the context and override them
+    let options = context.options().iter().cloned().chain(options).collect();
+    let (client, retry) = client(options, context.proxies())?;
+    let mut attempt = 1;
+    loop {
+        let (request, mut response, time, error) = client.send(message, context.jar());
+        if let Some(socket) = response.socket.take() {
+            context.open(name.to_owned(), socket);
+        }
+        let kind = error.as_ref().map_or(Fault::None, http::Error::kind);
+        let delay = retry.after(attempt, response.status, kind);
+        let error = error.map(|error| error.to_string()).unwrap_or_default();
+        let mut map = response.to_map();
+        let asserts = check(&mut map);
+        context.push(Record {
+            name: name.to_owned(),
+            request,
+            response,
+            time,
+            error,
+            kind,
+            asserts,
+            attempt,
+        });
+        match delay {
+            Some(delay) => std::thread::sleep(delay),
+            None => return Ok(Value::Map(map)),
+        }
+        attempt += 1;
+    }
+}
+
+/// Names of the options a request declaration accepts.
+pub(crate) const OPTIONS: [&str; 27] = [
+    "redirects",
+    "cookies",
+    "connect_timeout",
+    "read_timeout",
+    "timeout",
+    "ca",
+    "cert",
+    "key",
+    "insecure",
+    "sni",
+    "pins",
+    "resolve",
+    "dns_ttl",
+    "family",
+    "proxy",
+    "no_proxy",
+    "events",
+    "events_timeout",
+    "output",
+    "max_body",
+    "upload_rate",
+    "download_rate",
+    "latency",
+    "attempts",
+    "retry_on",
+    "backoff",
+    "backoff_max",
+];
+
+/// Builds the client that sends a request, and the policy it is retried with, from the values of its options.
+pub(crate) fn client(options: Vec<(String, Value)>, proxies: &http::Proxies) -> Result<(http::Client, http::Retry), String> {
+    let mut client = http::Client::default().proxies(proxies.clone());
+    let mut retry = http::Retry::default();
+    for (name, value) in options {
+        client = match (name.as_str(), value) {
+            ("redirects", Value::Integer(redirects)) if redirects >= 0 => client.redirects(redirects as usize),
+            ("redirects", Value::Boolean(false)) => client.redirects(0),
+            ("cookies", Value::Boolean(cookies)) => client.cookies(cookies),
+            ("connect_timeout", value) if timeout(&value).is_some() => client.connect_timeout(timeout(&value).flatten()),
+            ("read_timeout", value) if timeout(&value).is_some() => client.read_timeout(timeout(&value).flatten()),
+            ("timeout", value) if timeout(&value).is_some() => client.timeout(timeout(&value).flatten()),
+            ("ca", value) if strings(&value).is_some() => {
+                client.roots(strings(&value).unwrap_or_default().into_iter().map(PathBuf::from).collect())
+            }
+            ("cert", Value::String(cert)) => client.cert(Some(PathBuf::from(cert))),
+            ("key", Value::String(key)) => client.key(Some(PathBuf::from(key))),
+            ("insecure", Value::Boolean(insecure)) => client.insecure(insecure),
+            ("sni", Value::String(sni)) => client.sni(Some(sni)),
+            ("pins", value) if strings(&value).is_some() => client.pins(strings(&value).unwrap_or_default()),
+            ("resolve", value) if overrides(&value).is_some() => client.resolve(overrides(&value).unwrap_or_default()),
+            ("dns_ttl", value) if timeout(&value).is_some() => client.dns_ttl(timeout(&value).flatten()),
+            ("family", Value::String(family)) if ["any", "ipv4", "ipv6"].contains(&family.as_str()) => {
+                client.family(http::Family::from(family.as_str()))
+            }
+            ("proxy", Value::String(proxy)) if http::Proxy::parse(&proxy).is_some() => client.proxy(http::Proxy::parse(&proxy)),
+            ("proxy", Value::Boolean(false)) => client.proxy(None),
+            ("no_proxy", value) if strings(&value).is_some() => client.no_proxy(strings(&value).unwrap_or_default()),
+            ("events", Value::Integer(max)) if max >= 0 => client.events((max > 0).then_some(max as usize)),
+            ("events", Value::Boolean(false)) => client.events(None),
+            ("events_timeout", value) if timeout(&value).is_some() => client.events_timeout(timeout(&value).flatten()),
+            ("output", Value::String(file)) => client.output(Some(PathBuf::from(file))),
+            ("output", Value::Boolean(false)) => client.discard(true),
+            ("max_body", Value::Integer(max)) if max >= 0 => client.max_body(Some(max as usize)),
+            ("max_body", Value::Boolean(false)) => client.max_body(None),
+            ("upload_rate", value) if rate(&value).is_some() => client.upload_rate(rate(&value).flatten()),
+            ("download_rate", value) if rate(&value).is_some() => client.download_rate(rate(&value).flatten()),
+            ("latency", value) if timeout(&value).is_some() => client.latency(timeout(&value).flatten()),
+            ("attempts", Value::Integer(attempts)) if attempts > 0 => {
+                retry.attempts = attempts.min(u32::MAX as i64) as u32;
+                client
+            }
+            ("retry_on", value) if conditions(&value).is_some() => {
+                // the statuses and kinds given replace the default of retrying errors
+                (retry.statuses, retry.faults) = conditions(&value).unwrap_or_default();
+                client
+            }
+            ("backoff", value) if timeout(&value).is_some() => {
+                retry.backoff = timeout(&value).flatten().unwrap_or_default();
+                client
+            }
+            ("backoff_max", value) if timeout(&value).is_some() => {
+                retry.max = timeout(&value).flatten();
+                client
+            }
+            (option, value) if OPTIONS.contains(&option) => return Err(format!("option {} not support: {:?}", option, value)),
+            (option, _) => return Err(format!("option {} not found", option)),
+        };
+    }
+    Ok((client, retry))
+}
+
+/// A timeout option in milliseconds, zero or false waits without a limit.
+fn timeout(value: &Value) -> Option<Option<Duration>> {
+    match value {
+        Value::Integer(0) | Value::Boolean(false) => Some(None),
+        Value::Integer(millis) if *millis > 0 => Some(Some(Duration::from_millis(*millis as u64))),
+        _ => None,
+    }
+}
+
+/// A rate option in bytes a second, zero or false is not limited.
+fn rate(value: &Value) -> Option<Option<u64>> {
+    match value {
+        Value::Integer(0) | Value::Boolean(false) => Some(None),
+        Value::Integer(rate) if *rate > 0 => Some(Some(*rate as u64)),
+        _ => None,
+    }
+}
+
+/// The statuses and kinds of errors a request is retried on, one of them or an array of them.
+fn conditions(value: &Value) -> Option<(Vec<u16>, Vec<Fault>)> {
+    let items = match value {
+        Value::Array(items) => items.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+    let (mut statuses, mut faults) = (Vec::new(), Vec::new());
+    for item in items {
+        match item {
+            Value::Integer(status) if (100..=599).contains(status) => statuses.push(*status as u16),
+            Value::String(kind) if matches!(Fault::from(kind.as_str()), Fault::Timeout | Fault::Error) => {
+                faults.push(Fault::from(kind.as_str()))
+            }
+            _ => return None,
+        }
+    }
+    Some((statuses, faults))
+}
+
+/// A string or an array of strings, for options that take one or more.
+fn strings(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::String(string) => Some(vec![string.to_owned()]),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(string) => Some(string.to_owned()),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Overrides like `example.com:443:127.0.0.1`, the addresses hosts resolve to without asking.
+fn overrides(value: &Value) -> Option<Vec<(String, u16, Vec<std::net::IpAddr>)>> {
+    strings(value)?.iter().map(|text| http::Resolver::parse(text)).collect()
+}
+
+/// Finds every `{variable}` placeholder in the template with its range.
+pub(crate) fn placeholders(template: &str) -> Vec<(&str, Range<usize>)> {
+    static REGEX: OnceLock<regex::Regex> = OnceLock::new();
+    let regex = REGEX.get_or_init(|| regex::Regex::new(r"\{\s*([a-zA-Z_][a-zA-Z0-9_]*)\s*\}").unwrap());
+    let matches = regex.find_iter(template);
+    let mut ranges = Vec::new();
+    matches.for_each(|m| ranges.push((m.as_str()[1..m.as_str().len() - 1].trim(), m.range())));
+    ranges
+}
+
+/// Replaces every `{variable}` placeholder in the template with its value from the context,
+/// the values of a request message are escaped where they land in its url.
+fn interpolate(template: &str, context: &Context, message: bool) -> String {
+    let mut string = String::with_capacity(template.len());
+    let mut start = 0;
+    for (variable, range) in placeholders(template) {
+        string.push_str(&template[start..range.start]);
+        let value = context.get(variable).unwrap_or(&Value::Null).to_string();
+        match message {
+            true => string.push_str(&http::escape(&string, &value)),
+            false => string.push_str(&value),
+        }
+        start = range.end;
+    }
+    string.push_str(&template[start..]);
+    string
+}
+
 pub fn eval_block(exprs: &[Expr], context: &mut Context) -> Result<Value, String> {
     let mut result = Value::Null;
     for expr in exprs {
@@ -371,6 +585,9 @@ mod tests {
             (r#""hello world""#, Value::String(String::from("hello world"))),
             (r#""hello" + " world""#, Value::String(String::from("hello world"))),
             (r#""hello"+" world"+"!""#, Value::String(String::from("hello world!"))),
+            (r#""C:\new\table\\" + "x""#, Value::String(String::from(r"C:\new\table\\x"))),
+            (r#"let digits = "\d+\n"; digits"#, Value::String(String::from(r"\d+\n"))),
+            (r##"r#"say "\n""#"##, Value::String(String::from(r#"say "\n""#))),
         ];
         run_eval_tests(tests);
     }
@@ -406,6 +623,20 @@ mod tests {
         run_eval_tests(tests);
     }
 
+    #[test]
+    fn test_template_literal() {
+        let tests = vec![
+            ("let name = \"Bob\"; `Hello {name}!`", Value::String(String::from("Hello Bob!"))),
+            ("let age = 2; `{ \"age\": {age} }`", Value::String(String::from("{ \"age\": 2 }"))),
+            ("`{missing}`", Value::String(String::from("null"))),
+            (
+                "let id = 7; let body = `\n{\"id\": {id}}\n`; body",
+                Value::String(String::from("\n{\"id\": 7}\n")),
+            ),
+        ];
+        run_eval_tests(tests);
+    }
+
     #[test]
     fn test_array_literal() {
         let tests = vec![
@@ -0,0 +1,382 @@
+use crate::Expr;
+use crate::Kind;
+use crate::Parser;
+
+const INDENT: &str = "    ";
+
+/// Formats source text canonically, keeping its comments.
+pub fn format(text: &str) -> Result<String, String> {
+    let mut parser = Parser::with_comments(text);
+    let mut output = String::new();
+    // line and kind of the last printed item
+    let mut last: Option<(usize, Kind)> = None;
+    while parser.current_token().kind != Kind::Eof {
+        let token = parser.current_token().clone();
+        for comment in parser.take_comments(&token) {
+            separate(&mut output, last, comment.line, Kind::Comment);
+            output.push_str(&format!("//{}\n", comment.literal));
+            last = Some((comment.line, Kind::Comment));
+        }
+        separate(&mut output, last, token.line, token.kind);
+        match token.kind {
+            Kind::Request => {
+                let (name, (message, asserts, options)) = parser.parse_request_literal()?;
+                output.push_str(&request(&name, &message, &asserts, &options));
+            }
+            Kind::Test => {
+                let (name, exprs) = parser.parse_test_literal()?;
+                output.push_str(&format!("test {} {}\n", name, block(&exprs, 0)));
+            }
+            _ => {
+                let expr = parser.parse_expr(u8::MIN)?;
+                output.push_str(&statement(&expr, 0, false));
+                output.push('\n');
+            }
+        }
+        if parser.peek_token_is(Kind::Semi) {
+            parser.next_token();
+        }
+        // a comment after the statement stays on its line
+        if let Some(comment) = parser.take_trailing() {
+            output.pop();
+            output.push_str(&format!(" //{}\n", comment.literal));
+        }
+        last = Some((parser.current_token().end(), token.kind));
+        parser.next_token();
+    }
+    let token = parser.current_token().clone();
+    for comment in parser.take_comments(&token) {
+        separate(&mut output, last, comment.line, Kind::Comment);
+        output.push_str(&format!("//{}\n", comment.literal));
+        last = Some((comment.line, Kind::Comment));
+    }
+    Ok(output)
+}
+
+/// Keeps a single blank line where the text had one and around every request or test,
+/// comments right before a request or test stay attached to it.
+fn separate(output: &mut String, last: Option<(usize, Kind)>, line: usize, kind: Kind) {
+    let declaration = |kind| matches!(kind, Kind::Request | Kind::Test);
+    if let Some((end, previous)) = last {
+        if line > end + 1 || declaration(previous) || (declaration(kind) && previous != Kind::Comment) {
+            output.push('\n');
+        }
+    }
+}
+
+/// An item of a list, or a comment among them.
+enum Line {
+    Item(String),
+    Comment(String, bool),
+}
+
+impl Line {
+    fn of(expr: &Expr, item: impl FnOnce(&Expr) -> String) -> Line {
+        match expr {
+            Expr::Comment(comment, trailing) => Line::Comment(comment.to_owned(), *trailing),
+            expr => Line::Item(item(expr)),
+        }
+    }
+}
+
+/// The depth the items of a list are formatted at, one deeper when comments among them put them one to a line.
+fn inner<'a>(mut exprs: impl Iterator<Item = &'a Expr>, depth: usize) -> usize {
+    depth + exprs.any(|expr| matches!(expr, Expr::Comment(..))) as usize
+}
+
+/// Lays out the items of a list between the brackets, on one line if it may and there are no comments among them,
+/// else one to a line with the comments where they were.
+fn list(lines: &[Line], depth: usize, open: &str, close: &str, inline: bool) -> String {
+    if inline && lines.iter().all(|line| matches!(line, Line::Item(_))) {
+        let items = lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Item(item) => Some(item.as_str()),
+                Line::Comment(..) => None,
+            })
+            .collect::<Vec<&str>>();
+        return format!("{}{}{}", open, items.join(", "), close);
+    }
+    let mut rows: Vec<String> = Vec::new();
+    for line in lines {
+        match (line, rows.last_mut()) {
+            (Line::Comment(comment, true), Some(row)) => row.push_str(&format!(" //{}", comment)),
+            (Line::Comment(comment, _), _) => rows.push(format!("{}//{}", INDENT.repeat(depth + 1), comment)),
+            (Line::Item(item), _) => rows.push(format!("{}{},", INDENT.repeat(depth + 1), item)),
+        }
+    }
+    format!("{}\n{}\n{}{}", open, rows.join("\n"), INDENT.repeat(depth), close)
+}
+
+fn request(name: &str, message: &str, asserts: &[Expr], options: &[(String, Expr)]) -> String {
+    let mut output = format!("request {}", name);
+    if !options.is_empty() {
+        let inner = inner(options.iter().map(|(_, value)| value), 0);
+        let options = options
+            .iter()
+            .map(|(name, value)| Line::of(value, |value| format!("{}: {}", name, expr(value, inner))))
+            .collect::<Vec<Line>>();
+        output.push_str(&list(&options, 0, "(", ")", true));
+    }
+    output.push_str("`\n");
+    // only the request line and the headers are laid out, the text after them is sent as it is written
+    let mut rest = message.trim_start();
+    let mut lines = rest.split_inclusive('\n');
+    if let Some(line) = lines.next() {
+        output.push_str(&format!("{}{}\n", INDENT, line.trim()));
+        rest = &rest[line.len()..];
+        // the bytes after the url of a tcp message are its payload
+        if !line
+            .trim_start()
+            .get(..6)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("tcp://"))
+        {
+            for line in lines.take_while(|line| !line.trim().is_empty()) {
+                output.push_str(&format!("{}{}\n", INDENT, line.trim()));
+                rest = &rest[line.len()..];
+            }
+            // blank lines at the end are not sent
+            if rest.trim().is_empty() {
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+    output.push('`');
+    if !asserts.is_empty() {
+        let asserts = asserts
+            .iter()
+            .map(|assert| Line::of(assert, |assert| expr(assert, 1)))
+            .collect::<Vec<Line>>();
+        output.push_str(&list(&asserts, 0, "[", "]", false));
+    }
+    output.push('\n');
+    output
+}
+
+fn block(exprs: &[Expr], depth: usize) -> String {
+    if exprs.is_empty() {
+        return String::from("{}");
+    }
+    let last = exprs.iter().rposition(|expr| !matches!(expr, Expr::Comment(..)));
+    let mut rows: Vec<String> = Vec::new();
+    for (i, expr) in exprs.iter().enumerate() {
+        match (expr, rows.last_mut()) {
+            // a comment after a statement stays on its line
+            (Expr::Comment(comment, true), Some(row)) => row.push_str(&format!(" //{}", comment)),
+            _ => rows.push(format!(
+                "{}{}",
+                INDENT.repeat(depth + 1),
+                statement(expr, depth + 1, Some(i) == last)
+            )),
+        }
+    }
+    format!("{{\n{}\n{}}}", rows.join("\n"), INDENT.repeat(depth))
+}
+
+/// Formats a statement, the value of the last one in a block is left without a semicolon.
+fn statement(statement: &Expr, depth: usize, last: bool) -> String {
+    match statement {
+        Expr::Comment(..) | Expr::If(..) => expr(statement, depth),
+        Expr::Let(..) => format!("{};", expr(statement, depth)),
+        _ if last => expr(statement, depth),
+        _ => format!("{};", expr(statement, depth)),
+    }
+}
+
+fn expr(expr: &Expr, depth: usize) -> String {
+    let items = |exprs: &[Expr], open: &str, close: &str| {
+        let inner = inner(exprs.iter(), depth);
+        let lines = exprs.iter().map(|e| Line::of(e, |e| self::expr(e, inner))).collect::<Vec<Line>>();
+        list(&lines, depth, open, close, true)
+    };
+    match expr {
+        Expr::Array(exprs) => items(exprs, "[", "]"),
+        Expr::Map(pairs) => {
+            let inner = inner(pairs.iter().map(|(k, _)| k), depth);
+            let lines = pairs
+                .iter()
+                .map(|(k, v)| Line::of(k, |k| format!("{}: {}", self::expr(k, inner), self::expr(v, inner))))
+                .collect::<Vec<Line>>();
+            list(&lines, depth, "{", "}", true)
+        }
+        Expr::Index(left, index) => format!("{}[{}]", self::expr(left, depth), self::expr(index, depth)),
+        Expr::Field(left, field) => format!("{}.{}", self::expr(left, depth), field),
+        Expr::Let(name, value) => format!("let {} = {}", name, self::expr(value, depth)),
+        Expr::Unary(token, right) => format!("{}{}", token, self::expr(right, depth)),
+        Expr::Binary(token, left, right) => format!("{} {} {}", self::expr(left, depth), token, self::expr(right, depth)),
+        Expr::Paren(value) => format!("({})", self::expr(value, depth)),
+        Expr::If(condition, consequence, alternative) => {
+            let mut output = format!("if ({}) {}", self::expr(condition, depth), block(consequence, depth));
+            if !alternative.is_empty() {
+                output.push_str(&format!(" else {}", block(alternative, depth)));
+            }
+            output
+        }
+        Expr::Call(function, arguments) => items(arguments, &format!("{}(", function), ")"),
+        Expr::Comment(comment, _) => format!("//{}", comment.trim_end()),
+        _ => expr.to_string(),
+    }
+}
+
+#[test]
+fn test_format_source() {
+    let text = r#"
+// host of the service
+let host="httpbin.org"
+let   total = 1+2*3;
+
+
+request get(redirects:1+1 ,)`
+        GET http://{host}/get
+        Host: {host}
+          X-Indent: trimmed
+        Connection: close
+`[status==200,   body!=""];
+test call {
+    // call it
+    let response=get();if(response.status==200){ println("ok") } else {println("fail");response.status}
+    response.status
+}
+// trailing
+"#;
+    let expected = r#"// host of the service
+let host = "httpbin.org";
+let total = 1 + 2 * 3;
+
+request get(redirects: 1 + 1)`
+    GET http://{host}/get
+    Host: {host}
+    X-Indent: trimmed
+    Connection: close
+`[
+    status == 200,
+    body != "",
+]
+
+test call {
+    // call it
+    let response = get();
+    if (response.status == 200) {
+        println("ok")
+    } else {
+        println("fail");
+        response.status
+    }
+    response.status
+}
+
+// trailing
+"#;
+    let formatted = format(text).unwrap();
+    println!("{}", formatted);
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted).unwrap(), expected);
+}
+
+#[test]
+fn test_format_error() {
+    assert!(format("let = 1").is_err());
+}
+
+#[test]
+fn test_format_request_bytes() {
+    let text = r###"
+request post`
+        POST   http://{host}/post
+           Content-Type: application/json
+
+        {
+            "name": "basjoofan",   
+            "path": r"C:\new"
+        }
+
+
+`
+request form`POST http://{host}/post
+  Content-Type: application/x-www-form-urlencoded
+
+    name: basjoofan
+  age:  2
+    `
+request raw`
+    tcp://{host}:7
+  PING  
+    PONG
+`
+let pattern = r#"^\d{4} "year"$"#;
+"###;
+    let formatted = format(text).unwrap();
+    println!("{}", formatted);
+    assert!(formatted.contains(r##"let pattern = r#"^\d{4} "year"$"#;"##));
+    assert_eq!(format(&formatted).unwrap(), formatted);
+    let original = Parser::new(text).parse().unwrap().requests;
+    let requests = Parser::new(&formatted).parse().unwrap().requests;
+    assert_eq!(original.len(), requests.len());
+    let send = |message: &str| {
+        let (mut request, mut content) = crate::http::Request::from(message).unwrap();
+        let mut bytes = Vec::new();
+        request.write(&mut bytes, &mut content).unwrap();
+        (bytes, request.body)
+    };
+    for (name, (message, ..)) in original {
+        assert_eq!(send(&message), send(&requests[&name].0), "{}", name);
+    }
+}
+
+#[test]
+fn test_format_comments() {
+    let text = r#"
+let a = 1; // trailing one
+let b = {
+    "x": 1, // first
+    // inside map
+    "y": [2, // two
+        3],
+};
+request get(redirects: 1, // follow once
+)`GET http://{host}/get`[
+    // ok status
+    status == 200,
+    body != "", // not empty
+]
+test call {
+    let c = get(); // call it
+    if (c.status == 200) { // ok
+        c.status // done
+    }
+}
+"#;
+    let expected = r#"let a = 1; // trailing one
+let b = {
+    "x": 1, // first
+    // inside map
+    "y": [
+        2, // two
+        3,
+    ],
+};
+
+request get(
+    redirects: 1, // follow once
+)`
+    GET http://{host}/get
+`[
+    // ok status
+    status == 200,
+    body != "", // not empty
+]
+
+test call {
+    let c = get(); // call it
+    if (c.status == 200) {
+        // ok
+        c.status // done
+    }
+}
+"#;
+    let formatted = format(text).unwrap();
+    println!("{}", formatted);
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted).unwrap(), expected);
+}
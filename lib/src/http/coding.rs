@@ -0,0 +1,89 @@
+use std::io::Read;
+use std::io::Result;
+use std::io::Write;
+
+/// Bytes a body is decoded to at most when nothing else bounds it.
+pub const MAX: usize = 256 * 1024 * 1024;
+
+/// Decodes a body with the codings of a `Content-Encoding` header, an unknown coding and those before it are kept.
+/// Keeps at most `max` bytes of what it decodes, returns them with the size of the whole decoded body.
+pub fn decode(codings: &str, mut bytes: Vec<u8>, max: usize) -> Result<(Vec<u8>, usize)> {
+    let mut size = bytes.len();
+    // codings are listed in the order they were applied
+    for coding in codings.rsplit(',').map(|coding| coding.trim().to_ascii_lowercase()) {
+        (bytes, size) = match coding.as_str() {
+            "identity" => continue,
+            // the start of a coding can not be decoded by the one applied before it
+            "gzip" | "x-gzip" | "deflate" | "br" if size > bytes.len() => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "decoded body too large"))
+            }
+            "gzip" | "x-gzip" => read(flate2::read::MultiGzDecoder::new(bytes.as_slice()), max)?,
+            "deflate" => match read(flate2::read::ZlibDecoder::new(bytes.as_slice()), max) {
+                Ok(decoded) => decoded,
+                // some servers send deflate without the zlib wrapper
+                Err(_) => read(flate2::read::DeflateDecoder::new(bytes.as_slice()), max)?,
+            },
+            "br" => read(brotli::Decompressor::new(bytes.as_slice(), 4096), max)?,
+            _ => break,
+        };
+    }
+    Ok((bytes, size))
+}
+
+/// Reads at most `max` bytes and only counts the rest.
+fn read(mut reader: impl Read, max: usize) -> Result<(Vec<u8>, usize)> {
+    let mut bytes = Vec::new();
+    reader.by_ref().take(max as u64).read_to_end(&mut bytes)?;
+    let rest = std::io::copy(&mut reader, &mut std::io::sink())?;
+    let size = bytes.len() + rest as usize;
+    Ok((bytes, size))
+}
+
+/// Encodes a body with a coding of a `Content-Encoding` header, unknown codings leave it as it is.
+pub fn encode(coding: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+    match coding.trim().to_ascii_lowercase().as_str() {
+        "gzip" | "x-gzip" => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(bytes)?;
+            encoder.finish()
+        }
+        "deflate" => {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(bytes)?;
+            encoder.finish()
+        }
+        "br" => {
+            let mut encoded = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22);
+                encoder.write_all(bytes)?;
+            }
+            Ok(encoded)
+        }
+        _ => Ok(bytes.to_vec()),
+    }
+}
+
+#[test]
+fn test_encode_decode() {
+    let text = "hello hello hello hello".as_bytes().to_vec();
+    for coding in ["gzip", "deflate", "br", "identity"] {
+        let encoded = encode(coding, &text).unwrap();
+        assert_eq!(decode(coding, encoded, MAX).unwrap().0, text, "{}", coding);
+    }
+    let encoded = encode("br", &encode("gzip", &text).unwrap()).unwrap();
+    assert_eq!(decode("gzip, br", encoded, MAX).unwrap().0, text);
+    let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    raw.write_all(&text).unwrap();
+    assert_eq!(decode("deflate", raw.finish().unwrap(), MAX).unwrap().0, text);
+    // identity changes nothing, the codings around it are still decoded
+    let encoded = encode("gzip", &text).unwrap();
+    assert_eq!(decode("gzip, identity", encoded.to_owned(), MAX).unwrap().0, text);
+    assert_eq!(decode("identity, gzip", encoded.to_owned(), MAX).unwrap().0, text);
+    // an unknown coding keeps the body as it is from there
+    assert_eq!(decode("gzip, zstd", encoded.to_owned(), MAX).unwrap().0, encoded);
+    // what is decoded past the bound is only counted
+    assert_eq!(decode("gzip", encoded, 5).unwrap(), (b"hello".to_vec(), text.len()));
+    let encoded = encode("br", &encode("gzip", &text).unwrap()).unwrap();
+    assert!(decode("gzip, br", encoded, 5).is_err());
+}
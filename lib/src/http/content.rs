@@ -1,21 +1,88 @@
 use super::Error;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
 use std::io::Write;
 
 pub enum Content<'a> {
     Empty,
     Byte(Vec<u8>),
+    /// A file read while it is written, with its length
+    File(File, u64),
     Multipart(multipart::client::lazy::PreparedFields<'a>),
 }
 
 impl Content<'_> {
+    /// Whether the content can be written again, multipart fields are read while written.
+    pub fn replayable(&self) -> bool {
+        matches!(self, Content::Empty | Content::Byte(_) | Content::File(..))
+    }
+
+    /// Length of the content, unknown for multipart fields read from a stream.
+    pub fn length(&self) -> Option<u64> {
+        match self {
+            Content::Empty => Some(0),
+            Content::Byte(bytes) => Some(bytes.len() as u64),
+            Content::File(_, length) => Some(*length),
+            Content::Multipart(prepared) => prepared.content_len(),
+        }
+    }
+
+    /// Reads the content from its start, for framing that sends it in pieces of its own.
+    pub fn reader(&mut self) -> Result<Box<dyn Read + '_>, Error> {
+        Ok(match self {
+            Content::Empty => Box::new(std::io::empty()),
+            Content::Byte(bytes) => Box::new(bytes.as_slice()),
+            Content::File(file, length) => Box::new(rewind(file)?.take(*length)),
+            Content::Multipart(prepared) => Box::new(prepared),
+        })
+    }
+
+    /// Writes the content, in chunks if the length is unknown.
     pub fn write<W: Write>(&mut self, writer: &mut W) -> Result<(), Error> {
         match self {
             Content::Empty => Ok(()),
             Content::Byte(bytes) => Ok(writer.write_all(bytes).map_err(Error::WriteFailed)?),
-            Content::Multipart(ref mut prepared) => {
+            Content::File(file, length) => {
+                let copied = std::io::copy(&mut rewind(file)?.take(*length), writer).map_err(Error::WriteFailed)?;
+                match copied < *length {
+                    // the file got shorter than the length sent
+                    true => Err(Error::ReadFailed(std::io::ErrorKind::UnexpectedEof.into())),
+                    false => Ok(()),
+                }
+            }
+            Content::Multipart(ref mut prepared) if prepared.content_len().is_some() => {
                 std::io::copy(prepared, writer).map_err(Error::WriteFailed)?;
                 Ok(())
             }
+            Content::Multipart(ref mut prepared) => {
+                std::io::copy(prepared, &mut Chunked(&mut *writer)).map_err(Error::WriteFailed)?;
+                writer.write_all(b"0\r\n\r\n").map_err(Error::WriteFailed)
+            }
+        }
+    }
+}
+
+/// Starts a file over, so it can be sent again.
+fn rewind(file: &mut File) -> Result<&mut File, Error> {
+    file.rewind().map_err(Error::ReadFailed)?;
+    Ok(file)
+}
+
+/// Writes every buffer as one chunk of the chunked transfer coding.
+struct Chunked<W>(W);
+
+impl<W: Write> Write for Chunked<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !buf.is_empty() {
+            write!(self.0, "{:x}\r\n", buf.len())?;
+            self.0.write_all(buf)?;
+            self.0.write_all(b"\r\n")?;
         }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
     }
 }
@@ -0,0 +1,241 @@
+use super::Headers;
+use super::Scheme;
+use super::Url;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// Cookies stored from `Set-Cookie` headers and sent back to the urls they match.
+#[derive(Clone, Default)]
+pub struct Jar {
+    cookies: Vec<Cookie>,
+}
+
+#[derive(Clone)]
+struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    /// Whether the cookie had no `Domain` attribute and only matches its host
+    host_only: bool,
+    path: String,
+    expires: Option<SystemTime>,
+    secure: bool,
+}
+
+impl Jar {
+    /// Stores the cookies the headers of a response to the url set.
+    pub fn store(&mut self, url: &Url, headers: &Headers) {
+        let now = SystemTime::now();
+        for header in headers.iter().filter(|header| header.name.eq_ignore_ascii_case("Set-Cookie")) {
+            if let Some(cookie) = Cookie::parse(url, &header.value, now) {
+                self.cookies
+                    .retain(|c| (&c.name, &c.domain, &c.path) != (&cookie.name, &cookie.domain, &cookie.path));
+                if cookie.expires.is_none_or(|expires| expires > now) {
+                    self.cookies.push(cookie);
+                }
+            }
+        }
+    }
+
+    /// Names and values of the cookies sent to the url, longer paths first.
+    pub fn cookies(&self, url: &Url) -> Vec<(String, String)> {
+        let now = SystemTime::now();
+        let host = url.host.to_ascii_lowercase();
+        let path = url.path.as_str();
+        let mut cookies = self
+            .cookies
+            .iter()
+            .filter(|cookie| match cookie.host_only {
+                true => host == cookie.domain,
+                false => domain_match(&host, &cookie.domain),
+            })
+            .filter(|cookie| path_match(path, &cookie.path))
+            .filter(|cookie| !cookie.secure || matches!(url.scheme, Scheme::Https | Scheme::Wss))
+            .filter(|cookie| cookie.expires.is_none_or(|expires| expires > now))
+            .collect::<Vec<&Cookie>>();
+        cookies.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        cookies
+            .into_iter()
+            .map(|cookie| (cookie.name.to_owned(), cookie.value.to_owned()))
+            .collect()
+    }
+
+    /// Value of the `Cookie` header for a request to the url.
+    pub fn header(&self, url: &Url) -> Option<String> {
+        let cookies = self.cookies(url);
+        match cookies.is_empty() {
+            true => None,
+            false => Some(
+                cookies
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect::<Vec<String>>()
+                    .join("; "),
+            ),
+        }
+    }
+}
+
+impl Cookie {
+    /// Parses a `Set-Cookie` value, it is ignored if its domain does not match the url.
+    fn parse(url: &Url, text: &str, now: SystemTime) -> Option<Cookie> {
+        let mut attributes = text.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let host = url.host.to_ascii_lowercase();
+        let mut cookie = Cookie {
+            name: name.to_owned(),
+            value: value.trim().to_owned(),
+            domain: host.to_owned(),
+            host_only: true,
+            path: default_path(&url.path),
+            expires: None,
+            secure: false,
+        };
+        let mut max_age = None;
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "expires" => cookie.expires = cookie.expires.or(date(value)),
+                "max-age" => {
+                    max_age = value.parse::<i64>().ok().map(|age| match u64::try_from(age) {
+                        Ok(age) if age > 0 => now + Duration::from_secs(age),
+                        _ => SystemTime::UNIX_EPOCH,
+                    })
+                }
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_match(&host, &domain) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_owned(),
+                "secure" => cookie.secure = true,
+                _ => {}
+            }
+        }
+        // max-age wins over expires
+        cookie.expires = max_age.or(cookie.expires);
+        Some(cookie)
+    }
+}
+
+/// Directory of the request path, the path of a cookie without one.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => String::from("/"),
+        Some(i) => path[..i].to_owned(),
+    }
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.') && host.parse::<std::net::IpAddr>().is_err())
+}
+
+fn path_match(path: &str, cookie: &str) -> bool {
+    path == cookie || (path.starts_with(cookie) && (cookie.ends_with('/') || path[cookie.len()..].starts_with('/')))
+}
+
+/// Parses a cookie date like `Wed, 21 Oct 2015 07:28:00 GMT`, tokens may come in any order.
+fn date(text: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+    let (mut time, mut day, mut month, mut year) = (None, None, None, None);
+    for token in text
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == ':'))
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token.chars().all(|c| c.is_ascii_digit());
+        if time.is_none() && token.contains(':') {
+            let parts = token
+                .split(':')
+                .map(|part| part.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()?;
+            if let [hour, minute, second] = parts[..] {
+                time = Some(hour * 3600 + minute * 60 + second);
+            }
+        } else if day.is_none() && digits && token.len() <= 2 {
+            day = token.parse::<u64>().ok();
+        } else if month.is_none() && token.len() >= 3 && MONTHS.contains(&token[..3].to_ascii_lowercase().as_str()) {
+            month = MONTHS
+                .iter()
+                .position(|m| *m == token[..3].to_ascii_lowercase())
+                .map(|m| m as u64 + 1);
+        } else if year.is_none() && digits && (token.len() == 2 || token.len() == 4) {
+            year = token.parse::<u64>().ok().map(|year| match year {
+                0..=69 => year + 2000,
+                70..=99 => year + 1900,
+                _ => year,
+            });
+        }
+    }
+    let (time, day, month, year) = (time?, day?, month?, year?);
+    if !(1..=31).contains(&day) || year < 1601 || time >= 24 * 3600 {
+        return None;
+    }
+    let days = super::time::days(year, month, day);
+    let seconds = u64::try_from(days * 86400 + time as i64).unwrap_or_default();
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+#[test]
+fn test_cookie_date() {
+    let expires = |text| date(text).map(|time| time.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs());
+    assert_eq!(expires("Wed, 21 Oct 2015 07:28:00 GMT"), Some(1445412480));
+    assert_eq!(expires("Thu, 01-Jan-1970 00:00:01 GMT"), Some(1));
+    assert_eq!(expires("Sunday, 06-Nov-94 08:49:37 GMT"), Some(784111777));
+    assert_eq!(expires("not a date"), None);
+}
+
+#[test]
+fn test_cookie_jar() {
+    let headers = |values: &[&str]| {
+        let mut headers = Headers::default();
+        for value in values {
+            headers.insert(super::Header {
+                name: String::from("set-cookie"),
+                value: value.to_string(),
+            });
+        }
+        headers
+    };
+    let mut jar = Jar::default();
+    jar.store(
+        &Url::try_from("http://www.example.com/account/login").unwrap(),
+        &headers(&[
+            "session=abc; Path=/; HttpOnly",
+            "theme=dark; Domain=.example.com; Path=/",
+            "local=1",
+            "token=xyz; Secure; Path=/",
+            "other=1; Domain=example.org",
+            "gone=1; Max-Age=0",
+        ]),
+    );
+    assert_eq!(
+        jar.header(&Url::try_from("http://www.example.com/account/me").unwrap()).as_deref(),
+        Some("local=1; session=abc; theme=dark")
+    );
+    assert_eq!(
+        jar.header(&Url::try_from("https://www.example.com/").unwrap()).as_deref(),
+        Some("session=abc; theme=dark; token=xyz")
+    );
+    assert_eq!(
+        jar.header(&Url::try_from("http://api.example.com/").unwrap()).as_deref(),
+        Some("theme=dark")
+    );
+    assert_eq!(jar.header(&Url::try_from("http://example.org/").unwrap()), None);
+    jar.store(
+        &Url::try_from("http://www.example.com/").unwrap(),
+        &headers(&["session=; Expires=Thu, 01 Jan 1970 00:00:00 GMT", "theme=light; Domain=example.com"]),
+    );
+    assert_eq!(
+        jar.header(&Url::try_from("http://www.example.com/").unwrap()).as_deref(),
+        Some("theme=light")
+    );
+}
@@ -1,3 +1,5 @@
+use super::Fault;
+
 macro_rules! http_error {
     ($($name: ident => $description: expr,)+) => {
         #[derive(Debug)]
@@ -28,10 +30,41 @@ macro_rules! http_error {
 
 http_error! {
     InvalidUrlHost => "invalid url host",
+    InvalidUrlPort => "invalid url port",
+    InvalidUrlScheme => "invalid url scheme",
     HostNotFound => "host not found",
     TcpConnectFailed => "tcp connect failed",
     NoConnectionAvailable => "no connection available",
     TlsHandshakeFailed => "tls handshake failed",
     WriteFlushFailed => "write flush failed",
     SetReadTimeoutFailed => "set read timeout failed",
+    ConnectionClosed => "connection closed",
+    InvalidChunkSize => "invalid chunk size",
+    ContentDecodeFailed => "content decode failed",
+    ConnectTimeout => "connect timed out",
+    DeadlineExceeded => "deadline exceeded",
+    StreamReset => "stream reset",
+    InvalidFrame => "invalid frame",
+    HeaderDecodeFailed => "header decode failed",
+    InvalidCertificate => "invalid certificate",
+    InvalidPrivateKey => "invalid private key",
+    ProxyConnectFailed => "proxy connect failed",
+    ProxyAuthFailed => "proxy authentication failed",
+    UnixConnectFailed => "unix connect failed",
+    UpgradeFailed => "upgrade failed",
+}
+
+impl Error {
+    /// The kind of the error, waits that ran out of time are told apart from other failures.
+    pub fn kind(&self) -> Fault {
+        match self {
+            Error::ReadFailed(error) | Error::WriteFailed(error)
+                if matches!(error.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) =>
+            {
+                Fault::Timeout
+            }
+            Error::ConnectTimeout | Error::DeadlineExceeded => Fault::Timeout,
+            _ => Fault::Error,
+        }
+    }
 }
@@ -0,0 +1,135 @@
+use crate::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How much of an event stream is read before its response returns, a stream the server ends returns earlier.
+#[derive(Clone, Copy, Default)]
+pub struct Limit {
+    /// Events read at most, none reads them all
+    pub max: Option<usize>,
+    /// How long events are read for, from the end of the response head
+    pub time: Option<Duration>,
+}
+
+/// An event of a `text/event-stream` body.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    /// The last id the stream set, events without an id field keep the one before
+    pub id: String,
+    /// The event type, `message` if the event names none
+    pub event: String,
+    /// The data lines of the event, joined with line feeds
+    pub data: String,
+    /// The reconnection time in milliseconds the event asked for
+    pub retry: Option<u64>,
+}
+
+impl Event {
+    pub fn to_map(&self) -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.insert(String::from("id"), Value::String(self.id.to_owned()));
+        map.insert(String::from("event"), Value::String(self.event.to_owned()));
+        map.insert(String::from("data"), Value::String(self.data.to_owned()));
+        if let Some(retry) = self.retry {
+            map.insert(String::from("retry"), Value::Integer(retry as i64));
+        }
+        map
+    }
+}
+
+/// Builds events from the lines of a stream as they come.
+#[derive(Default)]
+pub struct Decoder {
+    /// Whether a line was taken, the first may start with a byte order mark
+    started: bool,
+    id: String,
+    event: String,
+    data: String,
+    retry: Option<u64>,
+}
+
+impl Decoder {
+    /// Takes a line without its end, an empty line dispatches the event the lines before it built.
+    pub fn line(&mut self, line: &str) -> Option<Event> {
+        let line = match std::mem::replace(&mut self.started, true) {
+            true => line,
+            false => line.strip_prefix('\u{feff}').unwrap_or(line),
+        };
+        if line.is_empty() {
+            let event = std::mem::take(&mut self.event);
+            let retry = self.retry.take();
+            // an event without data is not dispatched
+            if self.data.is_empty() {
+                return None;
+            }
+            let mut data = std::mem::take(&mut self.data);
+            data.pop();
+            return Some(Event {
+                id: self.id.to_owned(),
+                event: if event.is_empty() { String::from("message") } else { event },
+                data,
+                retry,
+            });
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            // a comment, like the keep alive lines of some servers
+            "" => {}
+            "event" => self.event = value.to_owned(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.id = value.to_owned(),
+            "retry" if !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()) => self.retry = value.parse().ok(),
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Parses the events of a whole body, a last event without an empty line after it is dropped.
+pub fn parse(text: &str) -> Vec<Event> {
+    let mut decoder = Decoder::default();
+    lines(text).filter_map(|line| decoder.line(line)).collect()
+}
+
+/// Splits text at line feeds, carriage returns and both together, like an event stream does.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split('\n')
+        .flat_map(|line| line.strip_suffix('\r').unwrap_or(line).split('\r'))
+        // the text after the last line end is not a line
+        .take(text.matches('\n').count() + text.matches('\r').count() - text.matches("\r\n").count())
+}
+
+#[test]
+fn test_event_parse() {
+    let text = "\u{feff}: ping\r\nid: 1\r\ndata: first\r\ndata:  line\r\n\r\nevent: update\nretry: 3000\ndata\n\nid\nevent: skipped\n\ndata: {\"n\": 3}\rid: 3\r\rdata: cut";
+    let events = parse(text);
+    assert_eq!(
+        events,
+        vec![
+            Event {
+                id: String::from("1"),
+                event: String::from("message"),
+                data: String::from("first\n line"),
+                retry: None,
+            },
+            Event {
+                id: String::from("1"),
+                event: String::from("update"),
+                data: String::new(),
+                retry: Some(3000),
+            },
+            Event {
+                id: String::from("3"),
+                event: String::from("message"),
+                data: String::from("{\"n\": 3}"),
+                retry: None,
+            },
+        ]
+    );
+}
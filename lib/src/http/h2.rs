@@ -0,0 +1,449 @@
+use super::response;
+use super::Collect;
+use super::Content;
+use super::Error;
+use super::Header;
+use super::Headers;
+use super::Request;
+use super::Response;
+use super::Session;
+use super::Sink;
+use super::Stream;
+use super::Throttle;
+use std::collections::VecDeque;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::time::Duration;
+
+/// Sent first on every connection, followed by the settings of the client.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+/// Window and frame size a connection starts with until the server settings change them.
+const WINDOW: i64 = 65535;
+const FRAME: usize = 16384;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+/// Header fields that only mean something to an HTTP/1.1 connection.
+const HOP: [&str; 6] = [
+    "host",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Names and values of a decoded header block.
+type Fields = Vec<(Vec<u8>, Vec<u8>)>;
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    id: u32,
+    payload: Vec<u8>,
+}
+
+/// An HTTP/2 connection, every request opens a new stream on it.
+/// A stream is received before the next one is opened, so only one is in flight at a time:
+/// the connection belongs to the pool of one virtual user, which sends one request after another.
+pub struct Connection {
+    reader: BufReader<Stream>,
+    decoder: fluke_hpack::Decoder<'static>,
+    /// Frames written since the last flush
+    out: Vec<u8>,
+    /// Frames of streams read while waiting for the window to send a body
+    pending: VecDeque<Frame>,
+    /// Id of the last stream opened, zero before the first
+    id: u32,
+    /// How many bytes the server accepts on the connection and on the open stream
+    window: i64,
+    stream: i64,
+    /// Window new streams start with
+    initial: i64,
+    /// Largest frame payload the server accepts
+    size: usize,
+    /// Whether the server has gone away
+    closed: bool,
+}
+
+impl Connection {
+    /// Starts HTTP/2 on a connection, server push is turned off.
+    pub fn handshake(reader: BufReader<Stream>) -> Result<Connection, Error> {
+        let mut connection = Connection {
+            reader,
+            decoder: fluke_hpack::Decoder::new(),
+            out: PREFACE.to_vec(),
+            pending: VecDeque::new(),
+            id: 0,
+            window: WINDOW,
+            stream: WINDOW,
+            initial: WINDOW,
+            size: FRAME,
+            closed: false,
+        };
+        connection.frame(SETTINGS, 0, 0, &[0, 2, 0, 0, 0, 0]);
+        connection.flush()?;
+        Ok(connection)
+    }
+
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.reader.get_ref().set_timeout(timeout)
+    }
+
+    pub fn throttle(&mut self, throttle: Throttle) {
+        self.reader.get_mut().throttle(throttle)
+    }
+
+    pub fn throttled(&self) -> Duration {
+        self.reader.get_ref().throttled()
+    }
+
+    pub fn session(&self) -> Option<&Session> {
+        self.reader.get_ref().session()
+    }
+
+    /// Whether an idle connection is still open and has nothing unread.
+    pub fn alive(&self) -> bool {
+        self.reader.buffer().is_empty() && self.reader.get_ref().alive()
+    }
+
+    /// Whether another stream can be opened on the connection.
+    pub fn reusable(&self) -> bool {
+        !self.closed && self.id < i32::MAX as u32 - 2
+    }
+
+    /// Opens a stream with the headers of the request and sends the content on it, returns the id of the stream.
+    pub fn send(&mut self, request: &mut Request, content: &mut Content) -> Result<u32, Error> {
+        request.prepare(content);
+        let length = content.length();
+        self.id = if self.id == 0 { 1 } else { self.id + 2 };
+        self.stream = self.initial;
+        let id = self.id;
+        let authority = request.headers.get("Host").map_or_else(|| request.url.authority(), str::to_owned);
+        let mut block = Vec::new();
+        for (name, value) in [
+            (":method", request.method.as_ref()),
+            (":scheme", request.url.scheme.as_ref()),
+            (":authority", authority.as_str()),
+            (":path", request.url.target().as_str()),
+        ] {
+            literal(&mut block, name, value);
+        }
+        for header in request.headers.iter() {
+            let name = header.name.to_ascii_lowercase();
+            if !HOP.contains(&name.as_str()) {
+                literal(&mut block, &name, &header.value);
+            }
+        }
+        let chunks = block.chunks(self.size).collect::<Vec<&[u8]>>();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut flags = if i + 1 == chunks.len() { END_HEADERS } else { 0 };
+            let kind = match i {
+                0 if length == Some(0) => {
+                    flags |= END_STREAM;
+                    HEADERS
+                }
+                0 => HEADERS,
+                _ => CONTINUATION,
+            };
+            self.frame(kind, flags, id, chunk);
+        }
+        self.flush()?;
+        if length == Some(0) {
+            return Ok(id);
+        }
+        // the content is read a frame at a time, the stream ends with the length or at the end of the content
+        let mut reader = content.reader()?;
+        let mut buffer = Vec::new();
+        let mut sent = 0;
+        loop {
+            let size = self.size.min(self.window.min(self.stream).max(0) as usize);
+            if size == 0 {
+                // waits for the server to open the window, frames of streams are kept for later
+                let frame = self.read()?;
+                if let Some(frame) = self.control(frame)? {
+                    self.pending.push_back(frame);
+                }
+                continue;
+            }
+            buffer.resize(length.map_or(size, |length| size.min((length - sent) as usize)), 0);
+            let read = reader.read(&mut buffer).map_err(Error::ReadFailed)?;
+            if read == 0 && length.is_some() {
+                // the file got shorter than the length sent
+                return Err(Error::ReadFailed(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+            sent += read as u64;
+            self.window -= read as i64;
+            self.stream -= read as i64;
+            let end = read == 0 || length == Some(sent);
+            self.frame(DATA, if end { END_STREAM } else { 0 }, id, &buffer[..read]);
+            self.flush()?;
+            if end {
+                return Ok(id);
+            }
+        }
+    }
+
+    /// Reads the response of a stream, its body through the sink as the frames arrive,
+    /// calls the function when its first frame arrives.
+    pub fn receive(&mut self, id: u32, sink: &Sink, mut f: Option<impl FnMut()>) -> Result<Response, Error> {
+        let mut head: Option<(u16, Headers)> = None;
+        let mut collect: Option<Collect> = None;
+        loop {
+            let frame = self.next()?;
+            let (stream, end) = (frame.id, frame.flags & END_STREAM != 0);
+            if stream == id {
+                if let Some(mut f) = f.take() {
+                    f()
+                }
+            }
+            match frame.kind {
+                HEADERS => {
+                    // every header block is decoded, the streams share the state of the decoder
+                    let fields = self.fields(frame)?;
+                    if stream != id {
+                        continue;
+                    }
+                    let mut status = 0;
+                    let mut headers = Vec::new();
+                    for (name, value) in fields {
+                        let (name, value) = (String::from_utf8_lossy(&name), String::from_utf8_lossy(&value));
+                        match name.as_ref() {
+                            ":status" => status = value.parse::<u16>().map_err(|_e| Error::InvalidFrame)?,
+                            _ if name.starts_with(':') => {}
+                            _ => headers.push(Header {
+                                name: name.into_owned(),
+                                value: value.into_owned(),
+                            }),
+                        }
+                    }
+                    match head.as_mut() {
+                        // a block after the headers carries the trailers
+                        Some((_, fields)) => headers.into_iter().for_each(|header| fields.insert(header)),
+                        // interim responses like 100 Continue precede the final one
+                        None if (100..200).contains(&status) && status != 101 => {}
+                        None => {
+                            let mut fields = Headers::default();
+                            headers.into_iter().for_each(|header| fields.insert(header));
+                            head = Some((status, fields));
+                        }
+                    }
+                    if end {
+                        break;
+                    }
+                }
+                DATA => {
+                    // what was received is given back to the windows of the connection and the stream
+                    let length = (frame.payload.len() as u32).to_be_bytes();
+                    if !frame.payload.is_empty() {
+                        self.frame(WINDOW_UPDATE, 0, 0, &length);
+                        if stream == id && !end {
+                            self.frame(WINDOW_UPDATE, 0, id, &length);
+                        }
+                        self.flush()?;
+                    }
+                    if stream == id {
+                        let collect = match collect.as_mut() {
+                            Some(collect) => collect,
+                            // an event stream is kept whole, like it is over HTTP/1.1 once it ends
+                            None => collect.insert(match &head {
+                                Some((_, headers)) if response::streamed(headers) => Sink::default().open()?,
+                                _ => sink.open()?,
+                            }),
+                        };
+                        collect.write_all(unpad(&frame)?).map_err(Error::WriteFailed)?;
+                        if end {
+                            break;
+                        }
+                    }
+                }
+                RST_STREAM if stream == id => return Err(Error::StreamReset),
+                PUSH_PROMISE => {
+                    // push was turned off in the settings
+                    self.closed = true;
+                    return Err(Error::InvalidFrame);
+                }
+                _ => {}
+            }
+        }
+        let (status, headers) = head.ok_or(Error::InvalidFrame)?;
+        match collect {
+            Some(collect) => Response::collected(String::from("HTTP/2.0"), status, String::new(), headers, collect, true),
+            None => Response::build(String::from("HTTP/2.0"), status, String::new(), headers, Vec::new(), true),
+        }
+    }
+
+    /// The next frame of a stream, frames of the connection are handled on the way.
+    fn next(&mut self) -> Result<Frame, Error> {
+        loop {
+            if let Some(frame) = self.pending.pop_front() {
+                return Ok(frame);
+            }
+            let frame = self.read()?;
+            if let Some(frame) = self.control(frame)? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Handles a frame of the connection, frames of streams are given back.
+    fn control(&mut self, frame: Frame) -> Result<Option<Frame>, Error> {
+        match frame.kind {
+            SETTINGS if frame.flags & ACK == 0 => {
+                for setting in frame.payload.chunks_exact(6) {
+                    let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+                    match u16::from_be_bytes([setting[0], setting[1]]) {
+                        // a new initial window changes the window of the open stream by the difference
+                        0x4 => {
+                            self.stream += value as i64 - self.initial;
+                            self.initial = value as i64;
+                        }
+                        0x5 => self.size = value as usize,
+                        _ => {}
+                    }
+                }
+                self.frame(SETTINGS, ACK, 0, &[]);
+                self.flush()?;
+            }
+            PING if frame.flags & ACK == 0 => {
+                self.frame(PING, ACK, 0, &frame.payload);
+                self.flush()?;
+            }
+            GOAWAY => {
+                self.closed = true;
+                // streams after the last one the server processed will never be answered
+                if word(&frame.payload) < self.id {
+                    return Err(Error::ConnectionClosed);
+                }
+            }
+            WINDOW_UPDATE => {
+                let increment = word(&frame.payload) as i64;
+                match frame.id {
+                    0 => self.window += increment,
+                    id if id == self.id => self.stream += increment,
+                    _ => {}
+                }
+            }
+            DATA | HEADERS | RST_STREAM | PUSH_PROMISE | CONTINUATION => return Ok(Some(frame)),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Reads a header block with its continuations and decodes it.
+    fn fields(&mut self, frame: Frame) -> Result<Fields, Error> {
+        let mut block = unpad(&frame)?.to_vec();
+        let mut flags = frame.flags;
+        while flags & END_HEADERS == 0 {
+            let continuation = self.next()?;
+            if continuation.kind != CONTINUATION || continuation.id != frame.id {
+                return Err(Error::InvalidFrame);
+            }
+            block.extend_from_slice(&continuation.payload);
+            flags = continuation.flags;
+        }
+        self.decoder.decode(&block).map_err(|_e| Error::HeaderDecodeFailed)
+    }
+
+    fn read(&mut self) -> Result<Frame, Error> {
+        let mut head = [0; 9];
+        self.reader.read_exact(&mut head).map_err(closed)?;
+        let length = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+        let mut payload = vec![0; length];
+        self.reader.read_exact(&mut payload).map_err(closed)?;
+        Ok(Frame {
+            kind: head[3],
+            flags: head[4],
+            id: word(&head[5..]),
+            payload,
+        })
+    }
+
+    /// Adds a frame to the ones written on the next flush.
+    fn frame(&mut self, kind: u8, flags: u8, id: u32, payload: &[u8]) {
+        self.out.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+        self.out.extend_from_slice(&[kind, flags]);
+        self.out.extend_from_slice(&id.to_be_bytes());
+        self.out.extend_from_slice(payload);
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        let stream = self.reader.get_mut();
+        stream.write_all(&self.out).map_err(Error::WriteFailed)?;
+        stream.flush().map_err(|_e| Error::WriteFlushFailed)?;
+        self.out.clear();
+        Ok(())
+    }
+}
+
+/// A stream id or window increment, without the reserved bit.
+fn word(bytes: &[u8]) -> u32 {
+    match bytes {
+        [a, b, c, d, ..] => u32::from_be_bytes([*a, *b, *c, *d]) & 0x7fff_ffff,
+        _ => 0,
+    }
+}
+
+fn closed(error: std::io::Error) -> Error {
+    match error.kind() {
+        std::io::ErrorKind::UnexpectedEof => Error::ConnectionClosed,
+        _ => Error::ReadFailed(error),
+    }
+}
+
+/// The payload of a frame without its padding and priority.
+fn unpad(frame: &Frame) -> Result<&[u8], Error> {
+    let mut payload = frame.payload.as_slice();
+    let mut pad = 0;
+    if frame.flags & PADDED != 0 {
+        pad = *payload.first().ok_or(Error::InvalidFrame)? as usize;
+        payload = &payload[1..];
+    }
+    if frame.kind == HEADERS && frame.flags & PRIORITY != 0 {
+        payload = payload.get(5..).ok_or(Error::InvalidFrame)?;
+    }
+    let end = payload.len().checked_sub(pad).ok_or(Error::InvalidFrame)?;
+    Ok(&payload[..end])
+}
+
+/// Adds a field as a literal the decoder does not index, so no table has to be kept in step with the server.
+fn literal(block: &mut Vec<u8>, name: &str, value: &str) {
+    block.push(0);
+    for string in [name, value] {
+        block.extend(fluke_hpack::encoder::encode_integer(string.len(), 7));
+        block.extend_from_slice(string.as_bytes());
+    }
+}
+
+#[test]
+fn test_frame_literal() {
+    let mut block = Vec::new();
+    literal(&mut block, ":path", "/get");
+    literal(&mut block, "x-long", &"a".repeat(200));
+    let fields = fluke_hpack::Decoder::new().decode(&block).unwrap();
+    assert_eq!(fields[0], (b":path".to_vec(), b"/get".to_vec()));
+    assert_eq!(fields[1], (b"x-long".to_vec(), vec![b'a'; 200]));
+    let frame = Frame {
+        kind: HEADERS,
+        flags: PADDED | PRIORITY,
+        id: 1,
+        payload: vec![2, 0, 0, 0, 0, 16, b'h', b'i', 0, 0],
+    };
+    assert_eq!(unpad(&frame).unwrap(), b"hi");
+    assert_eq!(word(&[0x80, 0, 0, 3]), 3);
+}
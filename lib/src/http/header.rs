@@ -16,6 +16,24 @@ impl Headers {
         self.inner.push(header)
     }
 
+    /// Inserts the header before all others.
+    pub fn prepend(&mut self, header: Header) {
+        self.inner.insert(0, header)
+    }
+
+    /// Value of the first header with the name, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.inner
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value.as_str())
+    }
+
+    /// Removes every header with the name, ignoring case.
+    pub fn remove(&mut self, name: &str) {
+        self.inner.retain(|header| !header.name.eq_ignore_ascii_case(name))
+    }
+
     pub fn iter(&self) -> Iter<'_, Header> {
         self.inner.iter()
     }
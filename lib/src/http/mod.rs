@@ -1,28 +1,57 @@
 mod client;
+mod coding;
 mod content;
+mod cookie;
 mod error;
+mod event;
+mod h2;
 mod header;
+mod pool;
+mod proxy;
 mod request;
 mod response;
+mod retry;
+mod sink;
 mod stream;
+mod throttle;
 mod time;
+mod tls;
 mod url;
+mod ws;
 
 use content::Content;
-use error::Error;
+use event::Event;
+use event::Limit;
 use header::Header;
 use header::Headers;
+use sink::Collect;
+use sink::Sink;
 use stream::Stream;
+use throttle::Shaper;
+use throttle::Throttle;
 use url::Url;
 
 pub use client::Client;
+pub use cookie::Jar;
+pub use error::Error;
+pub use proxy::Proxies;
+pub use proxy::Proxy;
 pub use request::Request;
 pub use response::Response;
+pub use response::FIELDS;
+pub use retry::Retry;
+pub use stream::Resolver;
 pub use time::Time;
+pub use tls::Session;
+pub use tls::Settings;
+pub use url::escape;
+#[cfg(test)]
+pub(crate) use ws::echo;
+pub use ws::Socket;
 
 macro_rules! http_type {
     ($type: ident $(,$name: ident => $value: literal)+) => {
-        #[derive(Default, Eq, PartialEq)]
+        #[derive(Default, Clone, Copy, Eq, PartialEq)]
         #[non_exhaustive]
         pub enum $type {
             #[default]
@@ -97,12 +126,38 @@ http_type! {
     Connect => "CONNECT"
 }
 
+impl Method {
+    /// Whether sending the request twice leaves the server as sending it once does.
+    pub fn idempotent(&self) -> bool {
+        matches!(
+            self,
+            Method::Get | Method::Head | Method::Options | Method::Trace | Method::Put | Method::Delete
+        )
+    }
+}
+
+http_type! {
+    Fault,
+    None => "",
+    Timeout => "timeout",
+    Error => "error"
+}
+
+http_type! {
+    Family,
+    Any => "any",
+    V4 => "ipv4",
+    V6 => "ipv6"
+}
+
 http_type! {
     Scheme,
     Http => "http",
-    Https => "https"
-    // Ws => "ws",
-    // Wss => "wss"
+    Https => "https",
+    HttpUnix => "http+unix",
+    Tcp => "tcp",
+    Ws => "ws",
+    Wss => "wss"
 }
 
 #[test]
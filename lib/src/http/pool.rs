@@ -0,0 +1,66 @@
+use super::h2::Connection;
+use super::Proxy;
+use super::Settings;
+use super::Stream;
+use super::Url;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::net::IpAddr;
+
+/// Most idle connections kept for one origin.
+const IDLE: usize = 8;
+
+/// Scheme, host and port of an origin, with the TLS settings, overridden addresses and proxy its connections were made with.
+pub type Key = (String, String, u16, Settings, Vec<IpAddr>, Option<Proxy>);
+
+thread_local! {
+    static POOL: RefCell<HashMap<Key, Vec<BufReader<Stream>>>> = RefCell::new(HashMap::new());
+    /// HTTP/2 connections, one for each origin carries all of its streams, one after another,
+    /// as the thread of a virtual user never shares them with another
+    static SESSIONS: RefCell<HashMap<Key, Connection>> = RefCell::new(HashMap::new());
+}
+
+pub fn key(url: &Url, settings: &Settings, addresses: Vec<IpAddr>, proxy: Option<&Proxy>) -> Key {
+    (
+        url.scheme.to_string(),
+        url.host.to_owned(),
+        url.port,
+        settings.to_owned(),
+        addresses,
+        proxy.cloned(),
+    )
+}
+
+/// Takes an idle connection to the origin of the url, skipping those the server has closed.
+pub fn take(key: &Key) -> Option<BufReader<Stream>> {
+    POOL.with_borrow_mut(|pool| {
+        let idle = pool.get_mut(key)?;
+        while let Some(reader) = idle.pop() {
+            if reader.buffer().is_empty() && reader.get_ref().alive() {
+                return Some(reader);
+            }
+        }
+        None
+    })
+}
+
+/// Gives a connection back to the pool of the current thread.
+pub fn give(key: Key, reader: BufReader<Stream>) {
+    POOL.with_borrow_mut(|pool| {
+        let idle = pool.entry(key).or_default();
+        if idle.len() < IDLE {
+            idle.push(reader);
+        }
+    })
+}
+
+/// Takes the HTTP/2 connection to the origin of the url, unless the server has closed it.
+pub fn take_session(key: &Key) -> Option<Connection> {
+    SESSIONS.with_borrow_mut(|sessions| sessions.remove(key).filter(Connection::alive))
+}
+
+/// Keeps an HTTP/2 connection for the next request to the origin.
+pub fn give_session(key: Key, connection: Connection) {
+    SESSIONS.with_borrow_mut(|sessions| sessions.insert(key, connection));
+}
@@ -1,10 +1,14 @@
+use super::coding;
 use super::Content;
 use super::Error;
 use super::Header;
 use super::Headers;
 use super::Method;
+use super::Proxy;
+use super::Scheme;
 use super::Url;
 use super::Version;
+use base64::Engine;
 use std::io::BufWriter;
 use std::io::Write;
 use std::path::Path;
@@ -24,13 +28,16 @@ pub struct Request {
 }
 
 impl Request {
-    /// Converts a message to an http request.
-    pub fn from(message: &str) -> (Request, Content) {
+    /// Converts a message to an http request, or to a raw one if its url is `tcp://`.
+    pub fn from(message: &str) -> Result<(Request, Content<'_>), Error> {
+        if let Some(raw) = Self::raw(message) {
+            return raw;
+        }
         let mut lines = message.trim().lines();
         if let Some(line) = lines.next() {
             let mut splits = line.split_whitespace();
             let method = Method::from(splits.next());
-            let url = Url::from(splits.next());
+            let url = Url::try_from(splits.next().unwrap_or_default())?;
             let version = Version::from(splits.next());
             let mut content_type = None;
             let mut headers = Headers::default();
@@ -47,6 +54,14 @@ impl Request {
                     }
                 }
             }
+            if !url.username.is_empty() && headers.get("Authorization").is_none() {
+                // the userinfo of the url becomes basic authorization
+                let credentials = format!("{}:{}", url.username, url.password.as_deref().unwrap_or_default());
+                headers.insert(Header {
+                    name: String::from("Authorization"),
+                    value: format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials)),
+                });
+            }
             let mut body = String::default();
             let content: Content;
             match content_type {
@@ -79,15 +94,39 @@ impl Request {
                     }
                 }
                 _ => {
-                    body = String::from_iter(lines);
+                    let lines = lines.by_ref().collect::<Vec<&str>>();
+                    body = lines.concat();
                     if body.trim().is_empty() {
                         content = Content::Empty;
                     } else {
-                        content = Content::Byte(body.as_bytes().to_owned());
+                        content = match file(&lines) {
+                            // a body of `@@text` is the text `@text`, not a file
+                            Some(text) if text.starts_with('@') => Content::Byte(text.as_bytes().to_owned()),
+                            // a file body is read from disk while it is sent, a content encoding is taken to be its own
+                            Some(path) => match std::fs::File::open(path.trim()) {
+                                Ok(file) => {
+                                    let length = file.metadata().map(|metadata| metadata.len()).unwrap_or_default();
+                                    Content::File(file, length)
+                                }
+                                Err(error) => {
+                                    let message = format!("{}: {}", path.trim(), error);
+                                    return Err(Error::ReadFailed(std::io::Error::new(error.kind(), message)));
+                                }
+                            },
+                            None => Content::Byte(body.as_bytes().to_owned()),
+                        };
                     }
                 }
             }
-            (
+            // the body is compressed when the message asks for a content encoding
+            let content = match (headers.get("Content-Encoding"), content) {
+                (Some(coding), Content::Byte(bytes)) => match coding::encode(coding, &bytes) {
+                    Ok(encoded) => Content::Byte(encoded),
+                    Err(_) => Content::Byte(bytes),
+                },
+                (_, content) => content,
+            };
+            Ok((
                 Request {
                     method,
                     url,
@@ -96,23 +135,120 @@ impl Request {
                     body,
                 },
                 content,
-            )
+            ))
         } else {
-            (Request::default(), Content::Empty)
+            Ok((Request::default(), Content::Empty))
+        }
+    }
+
+    /// Converts a message like `tcp://host:port` to a request that sends the bytes after the url as they are.
+    fn raw(message: &str) -> Option<Result<(Request, Content<'_>), Error>> {
+        let message = message.trim_start();
+        let (line, payload) = message.split_once('\n').unwrap_or((message, ""));
+        if !line.get(..6).is_some_and(|scheme| scheme.eq_ignore_ascii_case("tcp://")) {
+            return None;
         }
+        let content = match payload.is_empty() {
+            true => Content::Empty,
+            false => Content::Byte(payload.as_bytes().to_owned()),
+        };
+        let url = match Url::try_from(line.trim()) {
+            Ok(url) => url,
+            Err(error) => return Some(Err(error)),
+        };
+        let request = Request {
+            url,
+            body: payload.to_owned(),
+            ..Default::default()
+        };
+        Some(Ok((request, content)))
     }
 
-    pub fn write<W: Write>(&mut self, writer: W, mut content: Content) -> Result<(), Error> {
+    pub fn write<W: Write>(&mut self, writer: W, content: &mut Content) -> Result<(), Error> {
+        self.send(writer, content, None)
+    }
+
+    /// Writes the request to a proxy that forwards it, with the absolute url and the credentials of the proxy.
+    pub(super) fn forward<W: Write>(&mut self, writer: W, content: &mut Content, proxy: &Proxy) -> Result<(), Error> {
+        self.send(writer, content, Some(proxy))
+    }
+
+    fn send<W: Write>(&mut self, writer: W, content: &mut Content, proxy: Option<&Proxy>) -> Result<(), Error> {
+        self.prepare(content);
         let mut writer = BufWriter::new(writer);
-        write!(writer, "{} {} {}\r\n", self.method, self.url.path, self.version).map_err(Error::WriteFailed)?;
+        let target = match proxy {
+            Some(_) => format!("{}://{}{}", self.url.scheme, self.url.authority(), self.url.target()),
+            None => self.url.target(),
+        };
+        write!(writer, "{} {} {}\r\n", self.method, target, self.version).map_err(Error::WriteFailed)?;
         for header in self.headers.iter() {
             write!(writer, "{}: {}\r\n", header.name, header.value).map_err(Error::WriteFailed)?;
         }
+        // the credentials of the proxy are not part of the request
+        if let Some(authorization) = proxy.and_then(Proxy::authorization) {
+            write!(writer, "Proxy-Authorization: {}\r\n", authorization).map_err(Error::WriteFailed)?;
+        }
         write!(writer, "\r\n").map_err(Error::WriteFailed)?;
         content.write(&mut writer)?;
         writer.flush().map_err(|_e| Error::WriteFlushFailed)?;
         Ok(())
     }
+
+    /// Adds the headers every request is sent with, whatever the version.
+    pub(super) fn prepare(&mut self, content: &Content) {
+        self.identify();
+        self.frame(content);
+    }
+
+    /// Adds the `Host` and `User-Agent` headers, unless the message has them.
+    fn identify(&mut self) {
+        if self.headers.get("User-Agent").is_none() {
+            self.headers.insert(Header {
+                name: String::from("User-Agent"),
+                value: format!("basjoofan/{}", env!("CARGO_PKG_VERSION")),
+            });
+        }
+        if self.headers.get("Host").is_none() {
+            // a unix socket has no host, so it is named like the local one
+            let value = match self.url.scheme {
+                Scheme::HttpUnix => String::from("localhost"),
+                _ => self.url.authority(),
+            };
+            self.headers.prepend(Header {
+                name: String::from("Host"),
+                value,
+            });
+        }
+    }
+
+    /// Adds the header that tells where the content ends, unless the message has one.
+    fn frame(&mut self, content: &Content) {
+        if self.headers.get("Content-Length").is_some() || self.headers.get("Transfer-Encoding").is_some() {
+            return;
+        }
+        let (name, value) = match content.length() {
+            Some(0) if !matches!(self.method, Method::Post | Method::Put | Method::Patch) => return,
+            Some(length) => ("Content-Length", length.to_string()),
+            None => ("Transfer-Encoding", String::from("chunked")),
+        };
+        self.headers.insert(Header {
+            name: name.to_string(),
+            value,
+        });
+    }
+}
+
+/// What follows the `@` of a body that is one line like `@path`.
+fn file<'a>(lines: &[&'a str]) -> Option<&'a str> {
+    match lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>()[..]
+    {
+        [line] => line.strip_prefix('@').filter(|path| !path.trim().is_empty()),
+        _ => None,
+    }
 }
 
 #[test]
@@ -120,6 +256,104 @@ fn test_from_message_get() {
     let message = r#"
     GET http://httpbin.org/get
     Host: httpbin.org"#;
-    let (request, _content) = Request::from(message);
+    let (request, _content) = Request::from(message).unwrap();
     assert_eq!("GET", request.method.as_ref());
 }
+
+#[test]
+fn test_from_message_tcp() {
+    let (request, content) = Request::from("\n    tcp://127.0.0.1:6379\nPING\r\nINFO server\r\n").unwrap();
+    assert_eq!((request.url.scheme, request.url.port), (Scheme::Tcp, 6379));
+    assert_eq!(request.body, "PING\r\nINFO server\r\n");
+    assert!(matches!(content, Content::Byte(bytes) if bytes == b"PING\r\nINFO server\r\n"));
+    let (request, content) = Request::from("TCP://127.0.0.1:7").unwrap();
+    assert_eq!((request.url.scheme, request.body.as_str()), (Scheme::Tcp, ""));
+    assert!(matches!(content, Content::Empty));
+}
+
+#[test]
+fn test_write_content_file() {
+    let path = std::env::temp_dir().join(format!("basjoofan-body-{}.json", std::process::id()));
+    std::fs::write(&path, "{\"name\": \"basjoofan\"}").unwrap();
+    let message = format!(
+        "PUT http://httpbin.org/put\nContent-Type: application/json\n\n  @{}\n",
+        path.display()
+    );
+    let (mut request, mut content) = Request::from(&message).unwrap();
+    assert!(matches!(content, Content::File(_, 21)));
+    assert_eq!(request.body, format!("  @{}", path.display()));
+    // the file is read again when the request is sent again
+    for _ in 0..2 {
+        let mut bytes = Vec::new();
+        request.write(&mut bytes, &mut content).unwrap();
+        let bytes = String::from_utf8(bytes).unwrap();
+        assert!(bytes.contains("Content-Length: 21\r\n"));
+        assert!(bytes.ends_with("\r\n\r\n{\"name\": \"basjoofan\"}"));
+    }
+    std::fs::remove_file(&path).unwrap();
+    let (_, content) = Request::from("POST http://httpbin.org/post\n\n@a\n@b").unwrap();
+    assert!(matches!(content, Content::Byte(bytes) if bytes == b"@a@b"));
+    // a file that is not there fails the request instead of sending it empty
+    let error = Request::from(&message).err().unwrap();
+    assert!(matches!(&error, Error::ReadFailed(error) if error.kind() == std::io::ErrorKind::NotFound));
+    assert!(error.to_string().starts_with(&path.display().to_string()));
+    let (_, content) = Request::from("POST http://httpbin.org/post\n\n@@john\n").unwrap();
+    assert!(matches!(content, Content::Byte(bytes) if bytes == b"@john"));
+}
+
+#[test]
+fn test_write_content_length() {
+    let message = "POST http://httpbin.org/post\nHost: httpbin.org\n\n{\"name\": \"basjoofan\"}";
+    let (mut request, mut content) = Request::from(message).unwrap();
+    let mut bytes = Vec::new();
+    request.write(&mut bytes, &mut content).unwrap();
+    assert_eq!(
+        format!(
+            "POST /post HTTP/1.1\r\nHost: httpbin.org\r\nUser-Agent: basjoofan/{}\r\nContent-Length: 21\r\n\r\n{{\"name\": \"basjoofan\"}}",
+            env!("CARGO_PKG_VERSION")
+        ),
+        String::from_utf8(bytes).unwrap()
+    );
+    let (mut request, mut content) = Request::from("GET http://httpbin.org/get").unwrap();
+    let mut bytes = Vec::new();
+    request.write(&mut bytes, &mut content).unwrap();
+    assert_eq!(
+        format!(
+            "GET /get HTTP/1.1\r\nHost: httpbin.org\r\nUser-Agent: basjoofan/{}\r\n\r\n",
+            env!("CARGO_PKG_VERSION")
+        ),
+        String::from_utf8(bytes).unwrap()
+    );
+}
+
+#[test]
+fn test_write_content_encoding() {
+    let message = "POST http://httpbin.org/post\nContent-Encoding: gzip\n\nhello";
+    let (mut request, mut content) = Request::from(message).unwrap();
+    let mut bytes = Vec::new();
+    request.write(&mut bytes, &mut content).unwrap();
+    let (head, body) = bytes.split_at(bytes.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4);
+    assert!(String::from_utf8_lossy(head).contains(&format!("Content-Length: {}\r\n", body.len())));
+    assert_eq!(coding::decode("gzip", body.to_vec(), coding::MAX).unwrap().0, b"hello");
+    assert_eq!(request.body, "hello");
+}
+
+#[test]
+fn test_write_url_userinfo() {
+    let (mut request, mut content) = Request::from("GET http://user:pass@[::1]:8080/get?a=b#top\nUser-Agent: test").unwrap();
+    let mut bytes = Vec::new();
+    request.write(&mut bytes, &mut content).unwrap();
+    assert_eq!(
+        "GET /get?a=b HTTP/1.1\r\nHost: [::1]:8080\r\nUser-Agent: test\r\nAuthorization: Basic dXNlcjpwYXNz\r\n\r\n",
+        String::from_utf8(bytes).unwrap()
+    );
+}
+
+#[test]
+fn test_from_invalid_port() {
+    assert!(matches!(
+        Request::from("GET http://httpbin.org:99999/get"),
+        Err(Error::InvalidUrlPort)
+    ));
+    assert!(matches!(Request::from("tcp://127.0.0.1:abc\nPING"), Err(Error::InvalidUrlPort)));
+}
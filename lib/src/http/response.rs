@@ -1,6 +1,16 @@
+use super::coding;
+use super::event;
+use super::Collect;
 use super::Error;
+use super::Event;
+use super::Fault;
 use super::Header;
 use super::Headers;
+use super::Limit;
+use super::Method;
+use super::Session;
+use super::Sink;
+use super::Socket;
 use super::Stream;
 use crate::Parser;
 use crate::Source;
@@ -9,6 +19,26 @@ use std::collections::HashMap;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::Read;
+use std::io::Write;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Fields of the response map that request asserts and scripts can read.
+pub const FIELDS: [&str; 12] = [
+    "version",
+    "status",
+    "reason",
+    "headers",
+    "body",
+    "size",
+    "digest",
+    "json",
+    "redirects",
+    "cookies",
+    "tls",
+    "events",
+];
 
 #[derive(Default)]
 pub struct Response {
@@ -20,49 +50,204 @@ pub struct Response {
     pub reason: String,
     /// The response's headers
     pub headers: Headers,
-    /// The response's body
-    pub body: String,
+    /// The response's body, decoded from its content encoding
+    pub body: Vec<u8>,
+    /// The size of the body as it was received
+    pub wire: usize,
+    /// The size of the whole body, of which the body may keep only the start
+    pub size: usize,
+    /// The SHA-256 digest of the body as it was received, in lowercase hex, if a sink took it
+    pub digest: Option<String>,
+    /// The redirect responses followed before this one, with the url of each
+    pub redirects: Vec<(String, Response)>,
+    /// The cookies of the jar for the url of the response, after it was stored
+    pub cookies: Vec<(String, String)>,
+    /// What the TLS handshake of the connection agreed on, none for a plain connection
+    pub tls: Option<Session>,
+    /// The websocket a `101 Switching Protocols` response upgraded the connection to
+    pub socket: Option<Socket>,
+    /// The events of a `text/event-stream` body
+    pub events: Vec<Event>,
+    /// Whether the body ended before the connection did
+    delimited: bool,
 }
 
 impl Response {
-    /// Converts a stream to an http response.
-    pub fn from(mut reader: BufReader<Stream>, f: Option<impl FnMut()>) -> Result<Response, Error> {
-        let mut buf = Vec::with_capacity(1);
-        reader.read(&mut buf).map_err(Error::ReadFailed)?;
-        if let Some(mut f) = f {
+    /// Reads an http response to a request with the method from a stream,
+    /// leaving the stream at the end of the body so it can be reused.
+    pub fn from(reader: &mut BufReader<Stream>, method: &Method, f: Option<impl FnMut()>) -> Result<Response, Error> {
+        Self::listen(reader, method, &Limit::default(), &Sink::default(), f)
+    }
+
+    /// Reads a response like `from` does, but reads a `text/event-stream` body as its events come, up to the limit,
+    /// and any other body through the sink. The function is called at the first byte, and again at the first event of a stream.
+    pub(super) fn listen(
+        reader: &mut BufReader<Stream>,
+        method: &Method,
+        limit: &Limit,
+        sink: &Sink,
+        mut f: Option<impl FnMut()>,
+    ) -> Result<Response, Error> {
+        // waits for the first byte without consuming it
+        reader.fill_buf().map_err(Error::ReadFailed)?;
+        if let Some(f) = f.as_mut() {
             f()
         }
-        let mut line = String::from_utf8(buf).unwrap_or_default();
-        reader.read_line(&mut line).map_err(Error::ReadFailed)?;
-        let mut splits = line.split_whitespace();
-        let version = parse::<String>(splits.next());
-        let status = parse::<u16>(splits.next());
-        let reason = parse::<String>(splits.next());
-        // headers
-        let mut headers = Headers::default();
-        loop {
-            let mut line = String::new();
-            reader.read_line(&mut line).map_err(Error::ReadFailed)?;
-            if line.trim().is_empty() {
-                break;
-            } else if let Some((name, value)) = line.split_once(':') {
-                headers.insert(Header {
-                    name: name.trim().to_string(),
-                    value: value.trim().to_string(),
-                });
+        // interim responses like 100 Continue precede the final one
+        let (version, status, reason, mut headers) = loop {
+            let head = head(reader)?;
+            if !(100..200).contains(&head.1) || head.1 == 101 {
+                break head;
             }
+        };
+        let length = headers.get("Content-Length").and_then(|length| length.trim().parse::<usize>().ok());
+        let chunked = headers
+            .get("Transfer-Encoding")
+            .and_then(|encoding| encoding.rsplit(',').next())
+            .is_some_and(|encoding| encoding.trim().eq_ignore_ascii_case("chunked"));
+        if *method == Method::Head || matches!(status, 100..=199 | 204 | 304) {
+            Self::build(version, status, reason, headers, Vec::new(), true)
+        } else if streamed(&headers) {
+            let (body, delimited) = events(reader, &mut headers, length, chunked, limit, &mut f)?;
+            Self::build(version, status, reason, headers, body, delimited)
+        } else {
+            let mut collect = sink.open()?;
+            let delimited = self::body(reader, &mut headers, length, chunked, &mut collect)?;
+            Self::collected(version, status, reason, headers, collect, delimited)
         }
-        let mut body = String::default();
-        reader.read_to_string(&mut body).map_err(Error::ReadFailed)?;
-        Ok(Response {
+    }
+
+    /// Builds a response from a body taken through a sink.
+    pub(super) fn collected(
+        version: String,
+        status: u16,
+        reason: String,
+        headers: Headers,
+        collect: Collect,
+        delimited: bool,
+    ) -> Result<Response, Error> {
+        let truncated = collect.truncated();
+        let max = collect.max().unwrap_or(coding::MAX);
+        let (body, size, digest) = collect.finish()?;
+        let mut response = if truncated {
+            // the start of a body can not be decoded, it is kept as it came
+            let mut response = Self::assemble(version, status, reason, headers, body, size, delimited);
+            response.size = size;
+            response
+        } else {
+            Self::decode(version, status, reason, headers, body, delimited, max)?
+        };
+        response.digest = digest;
+        Ok(response)
+    }
+
+    /// Builds a response from what was received, decoding the body from its content encoding.
+    pub(super) fn build(
+        version: String,
+        status: u16,
+        reason: String,
+        headers: Headers,
+        body: Vec<u8>,
+        delimited: bool,
+    ) -> Result<Response, Error> {
+        Self::decode(version, status, reason, headers, body, delimited, coding::MAX)
+    }
+
+    /// Builds a response like `build` does, keeping at most `max` bytes of the decoded body.
+    fn decode(
+        version: String,
+        status: u16,
+        reason: String,
+        headers: Headers,
+        body: Vec<u8>,
+        delimited: bool,
+        max: usize,
+    ) -> Result<Response, Error> {
+        let wire = body.len();
+        let (body, size) = match headers.get("Content-Encoding") {
+            Some(codings) if !body.is_empty() => coding::decode(codings, body, max).map_err(|_e| Error::ContentDecodeFailed)?,
+            _ => {
+                let size = body.len();
+                (body, size)
+            }
+        };
+        let mut response = Self::assemble(version, status, reason, headers, body, wire, delimited);
+        response.size = size;
+        Ok(response)
+    }
+
+    fn assemble(version: String, status: u16, reason: String, headers: Headers, body: Vec<u8>, wire: usize, delimited: bool) -> Response {
+        let events = match headers.get("Content-Type") {
+            Some(content_type) if content_type.to_ascii_lowercase().starts_with("text/event-stream") => {
+                event::parse(&String::from_utf8_lossy(&body))
+            }
+            _ => Vec::new(),
+        };
+        Response {
             version,
             status,
             reason,
             headers,
+            size: body.len(),
+            digest: None,
             body,
+            wire,
+            redirects: Vec::new(),
+            cookies: Vec::new(),
+            tls: None,
+            socket: None,
+            events,
+            delimited,
+        }
+    }
+
+    /// Whether the connection can carry another request after this response.
+    pub fn reusable(&self) -> bool {
+        let connection = self.headers.get("Connection").map(|connection| connection.to_ascii_lowercase());
+        match self.version.as_str() {
+            "HTTP/1.1" => self.delimited && connection.as_deref() != Some("close"),
+            "HTTP/1.0" => self.delimited && connection.as_deref() == Some("keep-alive"),
+            _ => false,
+        }
+    }
+
+    /// The charset parameter of the content type.
+    fn charset(&self) -> Option<&str> {
+        let content_type = self.headers.get("Content-Type")?;
+        content_type.split(';').skip(1).find_map(|parameter| {
+            let (name, value) = parameter.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches('"'))
         })
     }
 
+    /// Whether the body is text, judged by the content type or by being valid UTF-8 without one.
+    pub fn textual(&self) -> bool {
+        match self
+            .headers
+            .get("Content-Type")
+            .map(|content_type| content_type.to_ascii_lowercase())
+        {
+            Some(content_type) => {
+                content_type.starts_with("text/")
+                    || ["json", "xml", "javascript", "x-www-form-urlencoded"]
+                        .iter()
+                        .any(|kind| content_type.contains(kind))
+                    || self.charset().is_some()
+            }
+            None => std::str::from_utf8(&self.body).is_ok(),
+        }
+    }
+
+    /// Decodes the body with the charset of the content type, UTF-8 by default.
+    pub fn text(&self) -> String {
+        let encoding = self
+            .charset()
+            .and_then(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+            .unwrap_or(encoding_rs::UTF_8);
+        let (text, _, _) = encoding.decode(&self.body);
+        text.into_owned()
+    }
+
     pub fn to_map(&self) -> HashMap<String, Value> {
         let mut map = HashMap::new();
         map.insert(String::from("version"), Value::String(self.version.to_string()));
@@ -78,16 +263,256 @@ impl Response {
             }
         }
         map.insert(String::from("headers"), Value::Map(headers));
-        map.insert(String::from("body"), Value::String(self.body.to_string()));
-        if let Ok(Source { exprs, .. }) = Parser::new(&self.body).parse() {
-            if let Some(expr) = exprs.first() {
-                map.insert(String::from("json"), expr.eval());
+        if self.textual() {
+            let text = self.text();
+            if let Ok(Source { exprs, .. }) = Parser::new(&text).parse() {
+                if let Some(expr) = exprs.first() {
+                    map.insert(String::from("json"), expr.eval());
+                }
             }
+            map.insert(String::from("body"), Value::String(text));
+        } else {
+            map.insert(String::from("body"), Value::Bytes(self.body.to_owned()));
         }
+        map.insert(String::from("size"), Value::Integer(self.size as i64));
+        let digest = self.digest.to_owned().map_or(Value::Null, Value::String);
+        map.insert(String::from("digest"), digest);
+        let redirects = self
+            .redirects
+            .iter()
+            .map(|(url, response)| {
+                let mut map = response.to_map();
+                map.insert(String::from("url"), Value::String(url.to_owned()));
+                Value::Map(map)
+            })
+            .collect();
+        map.insert(String::from("redirects"), Value::Array(redirects));
+        let cookies = self
+            .cookies
+            .iter()
+            .map(|(name, value)| (name.to_owned(), Value::String(value.to_owned())))
+            .collect();
+        map.insert(String::from("cookies"), Value::Map(cookies));
+        let mut tls = HashMap::new();
+        if let Some(session) = &self.tls {
+            let expires = session.expires.as_secs() as i64;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+            tls.insert(String::from("version"), Value::String(session.version.to_owned()));
+            tls.insert(String::from("cipher"), Value::String(session.cipher.to_owned()));
+            tls.insert(String::from("alpn"), Value::String(session.alpn.to_owned()));
+            tls.insert(String::from("subject"), Value::String(session.subject.to_owned()));
+            tls.insert(String::from("expires"), Value::Integer(expires));
+            // whole days the certificate is still valid for
+            tls.insert(String::from("days"), Value::Integer((expires - now).div_euclid(86400)));
+        }
+        map.insert(String::from("tls"), Value::Map(tls));
+        let events = self.events.iter().map(|event| Value::Map(event.to_map())).collect();
+        map.insert(String::from("events"), Value::Array(events));
         map
     }
 }
 
+/// Reads a status line and the headers after it.
+fn head(reader: &mut BufReader<Stream>) -> Result<(String, u16, String, Headers), Error> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(Error::ReadFailed)? == 0 {
+        return Err(Error::ConnectionClosed);
+    }
+    let mut splits = line.split_whitespace();
+    let version = parse::<String>(splits.next());
+    let status = parse::<u16>(splits.next());
+    let reason = parse::<String>(splits.next());
+    let mut headers = Headers::default();
+    fields(reader, &mut headers)?;
+    Ok((version, status, reason, headers))
+}
+
+/// Reads header fields up to an empty line.
+fn fields(reader: &mut BufReader<Stream>, headers: &mut Headers) -> Result<(), Error> {
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(Error::ReadFailed)?;
+        if line.trim().is_empty() {
+            return Ok(());
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.insert(Header {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+            });
+        }
+    }
+}
+
+/// Reads a body framed by chunks, a length or the end of the connection into a writer,
+/// returns whether it ended before the connection did.
+fn body(
+    reader: &mut BufReader<Stream>,
+    headers: &mut Headers,
+    length: Option<usize>,
+    chunked: bool,
+    writer: &mut impl Write,
+) -> Result<bool, Error> {
+    if chunked {
+        self::chunked(reader, headers, writer)?;
+        Ok(true)
+    } else if let Some(length) = length {
+        exact(reader, length, writer)?;
+        Ok(true)
+    } else {
+        std::io::copy(reader, writer).map_err(Error::ReadFailed)?;
+        Ok(false)
+    }
+}
+
+/// Copies as many bytes to a writer, fewer of them end the body too early.
+fn exact(reader: &mut BufReader<Stream>, length: usize, writer: &mut impl Write) -> Result<(), Error> {
+    let copied = std::io::copy(&mut reader.by_ref().take(length as u64), writer).map_err(Error::ReadFailed)?;
+    match copied < length as u64 {
+        true => Err(Error::ReadFailed(std::io::ErrorKind::UnexpectedEof.into())),
+        false => Ok(()),
+    }
+}
+
+/// Decodes a chunked body into a writer, the fields of its trailer are added to the headers.
+fn chunked(reader: &mut BufReader<Stream>, headers: &mut Headers, writer: &mut impl Write) -> Result<(), Error> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(Error::ReadFailed)? == 0 {
+            return Err(Error::ConnectionClosed);
+        }
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_e| Error::InvalidChunkSize)?;
+        if size == 0 {
+            break;
+        }
+        exact(reader, size, writer)?;
+        line.clear();
+        reader.read_line(&mut line).map_err(Error::ReadFailed)?;
+    }
+    fields(reader, headers)
+}
+
+/// Whether the body is an event stream that can be read as it comes, an encoded one is read whole.
+pub(super) fn streamed(headers: &Headers) -> bool {
+    headers.get("Content-Encoding").is_none()
+        && headers
+            .get("Content-Type")
+            .is_some_and(|content_type| content_type.to_ascii_lowercase().starts_with("text/event-stream"))
+}
+
+/// Reads the lines of an event stream until the limit or the end of the body, the first event calls the function.
+/// Returns the body up to the last line read, and whether it ended before the connection did.
+fn events(
+    reader: &mut BufReader<Stream>,
+    headers: &mut Headers,
+    length: Option<usize>,
+    chunked: bool,
+    limit: &Limit,
+    f: &mut Option<impl FnMut()>,
+) -> Result<(Vec<u8>, bool), Error> {
+    let deadline = limit.time.map(|time| Instant::now() + time);
+    let mut decoder = event::Decoder::default();
+    let (mut body, mut line) = (Vec::new(), Vec::new());
+    let mut count = 0;
+    // whether the last line ended with a carriage return, a line feed after it ends no line
+    let mut carriage = false;
+    // bytes left of the body, or of the chunk being read
+    let mut left = match chunked {
+        true => 0,
+        false => length.unwrap_or(usize::MAX),
+    };
+    loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok((body, false));
+            }
+            reader.get_ref().set_timeout(Some(remaining))?;
+        }
+        let available = match next(reader, headers, chunked, &mut left) {
+            Ok(Some(available)) => available,
+            Ok(None) => return Ok((body, chunked || length.is_some())),
+            Err(error) if error.kind() == Fault::Timeout && deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                return Ok((body, false))
+            }
+            Err(error) => return Err(error),
+        };
+        let mut used = 0;
+        let mut full = false;
+        for &byte in &reader.buffer()[..available] {
+            used += 1;
+            body.push(byte);
+            let ended = match byte {
+                b'\n' if std::mem::take(&mut carriage) => false,
+                b'\n' => true,
+                b'\r' => {
+                    carriage = true;
+                    true
+                }
+                byte => {
+                    carriage = false;
+                    line.push(byte);
+                    false
+                }
+            };
+            if !ended {
+                continue;
+            }
+            let event = decoder.line(&String::from_utf8_lossy(&line));
+            line.clear();
+            if event.is_some() {
+                count += 1;
+                if count == 1 {
+                    if let Some(f) = f.as_mut() {
+                        f()
+                    }
+                }
+                if limit.max.is_some_and(|max| count >= max) {
+                    full = true;
+                    break;
+                }
+            }
+        }
+        reader.consume(used);
+        if chunked || length.is_some() {
+            left -= used;
+        }
+        if full {
+            return Ok((body, false));
+        }
+    }
+}
+
+/// Waits for more of a streamed body, returns how many of the buffered bytes are of it, none at its end.
+fn next(reader: &mut BufReader<Stream>, headers: &mut Headers, chunked: bool, left: &mut usize) -> Result<Option<usize>, Error> {
+    if chunked && *left == 0 {
+        let mut line = String::new();
+        // the line end of the chunk before is read past
+        while line.trim().is_empty() {
+            line.clear();
+            if reader.read_line(&mut line).map_err(Error::ReadFailed)? == 0 {
+                return Err(Error::ConnectionClosed);
+            }
+        }
+        let size = line.split(';').next().unwrap_or_default().trim();
+        *left = usize::from_str_radix(size, 16).map_err(|_e| Error::InvalidChunkSize)?;
+        if *left == 0 {
+            fields(reader, headers)?;
+            return Ok(None);
+        }
+    }
+    if *left == 0 {
+        return Ok(None);
+    }
+    let buffer = reader.fill_buf().map_err(Error::ReadFailed)?;
+    match buffer.len() {
+        // a body without a length ends with the connection
+        0 if !chunked && *left == usize::MAX => Ok(None),
+        0 => Err(Error::ConnectionClosed),
+        length => Ok(Some(length.min(*left))),
+    }
+}
+
 fn parse<T: std::str::FromStr + std::default::Default>(str: Option<&str>) -> T {
     match str {
         Some(str) => str.parse::<T>().unwrap_or_default(),
@@ -100,7 +525,7 @@ fn test_from_message_json() {
     let message = r#"HTTP/1.1 200 OK
     Date: Sun, 21 Jul 2025 14:32:11 GMT
     Content-Type: application/json
-    Content-Length: 33
+    Content-Length: 47
     Connection: close
     Server: gunicorn/19.9.0
     Access-Control-Allow-Origin: *
@@ -111,13 +536,14 @@ fn test_from_message_json() {
     }
     "#;
     let response = Response::from(
-        BufReader::new(Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()))),
+        &mut BufReader::new(Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()))),
+        &Method::Get,
         None::<Box<dyn FnMut()>>,
     )
     .unwrap();
     assert_eq!(200, response.status);
     assert_eq!(7, response.headers.len());
-    assert_eq!("    {\n    \"origin\": \"104.28.152.141\"\n    }\n    ", response.body);
+    assert_eq!("    {\n    \"origin\": \"104.28.152.141\"\n    }\n    ", response.text());
     assert_eq!(
         Some(&Value::Map(HashMap::from_iter(vec![(
             String::from("origin"),
@@ -126,3 +552,195 @@ fn test_from_message_json() {
         response.to_map().get("json")
     )
 }
+
+#[test]
+fn test_from_message_keep_alive() {
+    let message = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirstHTTP/1.1 404 Not Found\r\nContent-Length: 6\r\n\r\nsecond";
+    let mut reader = BufReader::new(Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned())));
+    let first = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!((200, "first"), (first.status, first.text().as_str()));
+    assert!(first.reusable());
+    let second = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!((404, "second"), (second.status, second.text().as_str()));
+    assert!(matches!(
+        Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>),
+        Err(Error::ConnectionClosed)
+    ));
+}
+
+#[test]
+fn test_from_message_events() {
+    let message = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n\
+        d\r\ndata: a\r\n\r\nda\r\n10\r\nta: b\n\ndata: c\n\n\r\n0\r\n\r\n";
+    let reader = || BufReader::new(Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned())));
+    let response = Response::from(&mut reader(), &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    let data = |response: &Response| response.events.iter().map(|event| event.data.to_owned()).collect::<Vec<String>>();
+    assert_eq!(data(&response), vec!["a", "b", "c"]);
+    assert!(response.reusable());
+    let limit = Limit { max: Some(2), time: None };
+    let mut calls = 0;
+    let response = Response::listen(&mut reader(), &Method::Get, &limit, &Sink::default(), Some(|| calls += 1)).unwrap();
+    assert_eq!(data(&response), vec!["a", "b"]);
+    assert_eq!(response.text(), "data: a\r\n\r\ndata: b\n\n");
+    assert!(!response.reusable());
+    // once at the first byte and once at the first event
+    assert_eq!(calls, 2);
+    // a line ended by a carriage return alone does not swallow the line feed of a later line
+    let message = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: 24\r\n\r\ndata: a\rdata: b\n\nid: 1\n\n";
+    let mut reader = BufReader::new(Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned())));
+    let limit = Limit { max: Some(1), time: None };
+    let response = Response::listen(&mut reader, &Method::Get, &limit, &Sink::default(), None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!(data(&response), vec!["a\nb"]);
+    assert_eq!(response.text(), "data: a\rdata: b\n\n");
+}
+
+#[test]
+fn test_from_message_sink() {
+    let path = std::env::temp_dir().join(format!("basjoofan-output-{}.txt", std::process::id()));
+    let message = "HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhello world";
+    let reader = |message: &str| BufReader::new(Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned())));
+    let sink = Sink {
+        file: Some(path.to_owned()),
+        discard: false,
+        max: Some(5),
+    };
+    let response = Response::listen(
+        &mut reader(message),
+        &Method::Get,
+        &Limit::default(),
+        &sink,
+        None::<Box<dyn FnMut()>>,
+    )
+    .unwrap();
+    assert_eq!((response.text().as_str(), response.size, response.wire), ("hello", 11, 11));
+    assert_eq!(
+        response.digest.as_deref(),
+        Some("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
+    // a body that goes to a file is not kept whole, and another thread writes to its own file
+    let body = "a".repeat(100 * 1024);
+    let message = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+    let sink = Sink { max: None, ..sink };
+    let response = std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                Response::listen(
+                    &mut reader(&message),
+                    &Method::Get,
+                    &Limit::default(),
+                    &sink,
+                    None::<Box<dyn FnMut()>>,
+                )
+            })
+            .join()
+            .unwrap()
+    })
+    .unwrap();
+    assert_eq!((response.body.len(), response.size), (64 * 1024, body.len()));
+    let other = path.with_file_name(format!("basjoofan-output-{}-1.txt", std::process::id()));
+    assert_eq!(std::fs::read_to_string(&other).unwrap(), body);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&other).unwrap();
+    // a body cut short keeps its encoding
+    let message = "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nabcdef\r\n0\r\n\r\n";
+    let sink = Sink {
+        discard: true,
+        ..Default::default()
+    };
+    let response = Response::listen(
+        &mut reader(message),
+        &Method::Get,
+        &Limit::default(),
+        &sink,
+        None::<Box<dyn FnMut()>>,
+    )
+    .unwrap();
+    assert_eq!((response.body.len(), response.size), (0, 6));
+    assert!(response.reusable());
+    let map = response.to_map();
+    assert_eq!(map.get("size"), Some(&Value::Integer(6)));
+    assert!(matches!(map.get("digest"), Some(Value::String(digest)) if digest.len() == 64));
+    let message = "HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhello";
+    assert!(Response::listen(
+        &mut reader(message),
+        &Method::Get,
+        &Limit::default(),
+        &sink,
+        None::<Box<dyn FnMut()>>
+    )
+    .is_err());
+}
+
+#[test]
+fn test_from_message_chunked() {
+    let message = "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;name=value\r\nfirst\r\nA\r\n, second!!\r\n0\r\nChecksum: 42\r\n\r\nHTTP/1.1 204 No Content\r\nContent-Length: 5\r\n\r\n";
+    let mut reader = BufReader::new(Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned())));
+    let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!((200, "first, second!!"), (response.status, response.text().as_str()));
+    assert_eq!(Some("42"), response.headers.get("checksum"));
+    assert!(response.reusable());
+    let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!((204, ""), (response.status, response.text().as_str()));
+    let message = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHTTP/1.1 404 Not Found\r\n\r\n";
+    let mut reader = BufReader::new(Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned())));
+    let response = Response::from(&mut reader, &Method::Head, None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!((200, ""), (response.status, response.text().as_str()));
+    let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!(404, response.status);
+    assert!(!response.reusable());
+}
+
+#[test]
+fn test_from_message_binary() {
+    let mut message = b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=ISO-8859-1\r\nContent-Length: 5\r\n\r\ncaf\xe9!".to_vec();
+    message.extend(b"HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 4\r\n\r\n\x89PNG");
+    let mut reader = BufReader::new(Stream::Mock(std::io::Cursor::new(message)));
+    let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!(Some(&Value::String(String::from("café!"))), response.to_map().get("body"));
+    let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    assert!(!response.textual());
+    assert_eq!(Some(&Value::Bytes(b"\x89PNG".to_vec())), response.to_map().get("body"));
+}
+
+#[test]
+fn test_from_message_gzip() {
+    let body = coding::encode("gzip", br#"{"id": 7}"#).unwrap();
+    let mut message = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    message.extend(&body);
+    let mut reader = BufReader::new(Stream::Mock(std::io::Cursor::new(message)));
+    let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>).unwrap();
+    assert_eq!((body.len(), 9), (response.wire, response.body.len()));
+    assert_eq!(
+        Some(&Value::Map(HashMap::from_iter(vec![(String::from("id"), Value::Integer(7))]))),
+        response.to_map().get("json")
+    );
+}
+
+#[test]
+fn test_to_map_tls() {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let response = Response {
+        tls: Some(Session {
+            version: String::from("TLSv1.3"),
+            alpn: String::from("h2"),
+            subject: String::from("CN=example.com"),
+            expires: now + std::time::Duration::from_secs(40 * 86400 + 3600),
+            ..Session::default()
+        }),
+        ..Response::default()
+    };
+    let tls = match response.to_map().remove("tls") {
+        Some(Value::Map(tls)) => tls,
+        _ => unreachable!(),
+    };
+    assert_eq!(tls.get("version"), Some(&Value::String(String::from("TLSv1.3"))));
+    assert_eq!(tls.get("alpn"), Some(&Value::String(String::from("h2"))));
+    assert_eq!(tls.get("days"), Some(&Value::Integer(40)));
+    assert!(matches!(Response::default().to_map().remove("tls"), Some(Value::Map(tls)) if tls.is_empty()));
+}
@@ -0,0 +1,69 @@
+use super::Fault;
+use ring::rand::SecureRandom;
+use std::time::Duration;
+
+/// When a request is sent again, and how long is waited before.
+#[derive(Clone)]
+pub struct Retry {
+    /// Times the request is sent at most, the first one included
+    pub attempts: u32,
+    /// Statuses of responses that are retried
+    pub statuses: Vec<u16>,
+    /// Kinds of errors that are retried
+    pub faults: Vec<Fault>,
+    /// Waited before the second attempt, and twice as long before every one after
+    pub backoff: Duration,
+    /// Waited at most between two attempts
+    pub max: Option<Duration>,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            statuses: Vec::new(),
+            faults: vec![Fault::Error, Fault::Timeout],
+            backoff: Duration::from_millis(100),
+            max: None,
+        }
+    }
+}
+
+impl Retry {
+    /// How long to wait before sending again after this attempt, none if it is not retried.
+    pub fn after(&self, attempt: u32, status: u16, fault: Fault) -> Option<Duration> {
+        let retried = match fault {
+            Fault::None => self.statuses.contains(&status),
+            fault => self.faults.contains(&fault),
+        };
+        if !retried || attempt >= self.attempts {
+            return None;
+        }
+        let delay = self.backoff.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let delay = self.max.map_or(delay, |max| delay.min(max));
+        // equal jitter, half of the delay is waited for sure and the other half at random
+        let mut bytes = [0; 4];
+        let _ = ring::rand::SystemRandom::new().fill(&mut bytes);
+        let random = u32::from_be_bytes(bytes) as f64 / u32::MAX as f64;
+        Some(delay / 2 + (delay / 2).mul_f64(random))
+    }
+}
+
+#[test]
+fn test_retry_after() {
+    let retry = Retry {
+        attempts: 4,
+        statuses: vec![503],
+        max: Some(Duration::from_millis(300)),
+        ..Default::default()
+    };
+    let between = |delay: Option<Duration>, low: u64, high: u64| {
+        delay.is_some_and(|delay| delay >= Duration::from_millis(low) && delay <= Duration::from_millis(high))
+    };
+    assert!(between(retry.after(1, 503, Fault::None), 50, 100));
+    assert!(between(retry.after(2, 0, Fault::Timeout), 100, 200));
+    assert!(between(retry.after(3, 0, Fault::Error), 150, 300));
+    assert_eq!(retry.after(4, 503, Fault::None), None);
+    assert_eq!(retry.after(1, 500, Fault::None), None);
+    assert_eq!(Retry::default().after(1, 0, Fault::Error), None);
+}
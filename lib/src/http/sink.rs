@@ -0,0 +1,136 @@
+use super::Error;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::thread::ThreadId;
+
+/// Bytes of a body a response keeps when the body goes to a file and no other bound is set.
+const PREFIX: usize = 64 * 1024;
+
+/// Where response bodies go as they are read, and how much of them responses keep.
+#[derive(Clone, Default)]
+pub struct Sink {
+    /// A file every body is written to, the body of a later response replaces the one before,
+    /// each thread after the first writes to its own file numbered like `body-1.json`
+    pub file: Option<PathBuf>,
+    /// Whether bodies are dropped once they are hashed
+    pub discard: bool,
+    /// Bytes of a body a response keeps at most, none keeps the whole body unless it goes to a file
+    pub max: Option<usize>,
+}
+
+impl Sink {
+    /// Whether bodies are read like they always are, whole and without a digest.
+    pub fn whole(&self) -> bool {
+        self.file.is_none() && !self.discard && self.max.is_none()
+    }
+
+    /// Starts taking a body.
+    pub fn open(&self) -> Result<Collect, Error> {
+        let file = match &self.file {
+            Some(path) => Some(BufWriter::new(File::create(own(path)).map_err(Error::WriteFailed)?)),
+            None => None,
+        };
+        let max = match (self.discard, &self.file) {
+            (true, _) => Some(0),
+            // a body that is written out is not kept whole as well
+            (false, Some(_)) => Some(self.max.unwrap_or(PREFIX)),
+            (false, None) => self.max,
+        };
+        Ok(Collect {
+            bytes: Vec::new(),
+            size: 0,
+            max,
+            file,
+            digest: (!self.whole()).then(|| ring::digest::Context::new(&ring::digest::SHA256)),
+        })
+    }
+}
+
+/// The file the current thread writes a body to, threads never share one.
+fn own(path: &Path) -> PathBuf {
+    static FILES: OnceLock<Mutex<HashMap<PathBuf, Vec<ThreadId>>>> = OnceLock::new();
+    let mut files = FILES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    let threads = files.entry(path.to_path_buf()).or_default();
+    let thread = std::thread::current().id();
+    let index = match threads.iter().position(|id| *id == thread) {
+        Some(index) => index,
+        None => {
+            threads.push(thread);
+            threads.len() - 1
+        }
+    };
+    if index == 0 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// Takes a body as it is read, writing it to the file and hashing it, and keeps the start of it.
+pub struct Collect {
+    bytes: Vec<u8>,
+    size: usize,
+    max: Option<usize>,
+    file: Option<BufWriter<File>>,
+    digest: Option<ring::digest::Context>,
+}
+
+impl Collect {
+    /// Bytes of the body it keeps at most, none keeps the whole body.
+    pub fn max(&self) -> Option<usize> {
+        self.max
+    }
+
+    /// Whether more of the body was taken than kept.
+    pub fn truncated(&self) -> bool {
+        self.size > self.bytes.len()
+    }
+
+    /// Ends the body, returns the bytes kept, the size of the whole body and its SHA-256 digest in lowercase hex.
+    pub fn finish(self) -> Result<(Vec<u8>, usize, Option<String>), Error> {
+        if let Some(mut file) = self.file {
+            file.flush().map_err(Error::WriteFailed)?;
+        }
+        let digest = self
+            .digest
+            .map(|digest| digest.finish().as_ref().iter().map(|byte| format!("{:02x}", byte)).collect());
+        Ok((self.bytes, self.size, digest))
+    }
+}
+
+impl Write for Collect {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(buf)?;
+        }
+        if let Some(digest) = self.digest.as_mut() {
+            digest.update(buf);
+        }
+        let kept = self
+            .max
+            .map_or(buf.len(), |max| max.saturating_sub(self.bytes.len()).min(buf.len()));
+        self.bytes.extend_from_slice(&buf[..kept]);
+        self.size += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
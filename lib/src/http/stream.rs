@@ -1,9 +1,18 @@
 use super::Error;
+use super::Family;
+use super::Proxy;
 use super::Scheme;
+use super::Session;
+use super::Settings;
+use super::Shaper;
+use super::Throttle;
 use super::Url;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io::Read;
 use std::io::Write;
 use std::iter::{self, FusedIterator};
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::net::TcpStream;
 use std::net::ToSocketAddrs;
@@ -13,52 +22,212 @@ use std::time::Instant;
 
 const RACE: Duration = Duration::from_millis(200);
 
+/// Addresses of a host and port, with when they were resolved.
+type Resolved = HashMap<(String, u16), (Instant, Vec<SocketAddr>)>;
+
+thread_local! {
+    /// Addresses resolved on the current thread
+    static CACHE: RefCell<Resolved> = RefCell::new(HashMap::new());
+}
+
+/// Finds the addresses of a host.
+pub trait Resolve: Send + Sync {
+    fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolves hosts with the resolver of the system.
+struct System;
+
+impl Resolve for System {
+    fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+/// A host and port with the addresses it resolves to without asking, like `example.com:443:127.0.0.1`.
+pub type Override = (String, u16, Vec<IpAddr>);
+
+/// Resolves hosts with another resolver, unless they are overridden or were resolved before the cache expired.
+#[derive(Clone)]
+pub struct Resolver {
+    pub(super) inner: Arc<dyn Resolve>,
+    pub overrides: Vec<Override>,
+    /// How long resolved addresses are cached, none resolves on every connection
+    pub ttl: Option<Duration>,
+    /// The address family connections are made with
+    pub family: Family,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver {
+            inner: Arc::new(System),
+            overrides: Vec::new(),
+            ttl: None,
+            family: Family::Any,
+        }
+    }
+}
+
+impl Resolver {
+    /// Parses an override like `host:port:address[,address]`, addresses of IPv6 may be in brackets.
+    pub fn parse(text: &str) -> Option<Override> {
+        let (host, rest) = text.split_once(':')?;
+        let (port, addresses) = rest.split_once(':')?;
+        let addresses = addresses
+            .split(',')
+            .map(|address| address.trim().trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok())
+            .collect::<Option<Vec<IpAddr>>>()?;
+        Some((host.to_ascii_lowercase(), port.parse().ok()?, addresses))
+    }
+
+    /// The addresses the host is overridden with, empty if it is not.
+    pub fn overridden(&self, host: &str, port: u16) -> Vec<IpAddr> {
+        self.overrides
+            .iter()
+            .find(|(h, p, _)| h.eq_ignore_ascii_case(host) && *p == port)
+            .map(|(_, _, addresses)| addresses.to_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, Error> {
+        let overridden = self.overridden(host, port);
+        if !overridden.is_empty() {
+            return Ok(overridden.into_iter().map(|address| SocketAddr::new(address, port)).collect());
+        }
+        let key = (host.to_ascii_lowercase(), port);
+        if let Some(ttl) = self.ttl {
+            let cached = CACHE.with_borrow(|cache| {
+                cache
+                    .get(&key)
+                    .filter(|(resolved, _)| resolved.elapsed() < ttl)
+                    .map(|(_, addrs)| addrs.to_owned())
+            });
+            if let Some(addrs) = cached {
+                return Ok(addrs);
+            }
+        }
+        let addrs = self.inner.resolve(host, port).map_err(|_e| Error::HostNotFound)?;
+        if self.ttl.is_some() {
+            CACHE.with_borrow_mut(|cache| cache.insert(key, (Instant::now(), addrs.to_owned())));
+        }
+        Ok(addrs)
+    }
+}
+
 pub enum Stream {
     Plain {
         stream: TcpStream,
         resolve: Duration,
+        /// How long opening a tunnel through a proxy took
+        proxy: Duration,
+        /// What holds reads and writes back, none lets them go as fast as they can
+        shaper: Option<Box<Shaper>>,
     },
     Cipher {
         stream: TcpStream,
         resolve: Duration,
+        proxy: Duration,
+        /// How long the handshake took and what it agreed on
+        tls: Duration,
+        session: Box<Session>,
         attach: Box<rustls::ClientConnection>,
+        shaper: Option<Box<Shaper>>,
+    },
+    #[cfg(unix)]
+    Unix {
+        stream: std::os::unix::net::UnixStream,
+        shaper: Option<Box<Shaper>>,
     },
     #[cfg(test)]
     Mock(std::io::Cursor<Vec<u8>>),
 }
 
 impl Stream {
-    pub fn connect(url: &Url, connect_tiomeout: Option<Duration>, read_tiomeout: Option<Duration>) -> Result<Self, Error> {
+    pub fn connect(
+        url: &Url,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+        settings: &Settings,
+        resolver: &Resolver,
+        proxy: Option<&Proxy>,
+    ) -> Result<Self, Error> {
         let host = url.host.as_str();
         let port = url.port;
-
-        match url.scheme {
-            Scheme::Http => {
-                let (stream, resolve) = Self::connect_tcp(host, port, connect_tiomeout, read_tiomeout)?;
-                Ok(Stream::Plain { stream, resolve })
+        let attach = match url.scheme {
+            Scheme::Http | Scheme::Tcp | Scheme::Ws => None,
+            Scheme::Https => Some(Self::attach(host, settings, &[b"h2", b"http/1.1"])?),
+            // the upgrade to a websocket is a request of HTTP/1.1
+            Scheme::Wss => Some(Self::attach(host, settings, &[b"http/1.1"])?),
+            Scheme::HttpUnix => return Self::connect_unix(&url.socket(), read_timeout),
+        };
+        let (stream, resolve, proxy) = match proxy {
+            None => {
+                let (stream, resolve) = Self::connect_tcp(host, port, connect_timeout, read_timeout, resolver)?;
+                (stream, resolve, Duration::ZERO)
+            }
+            // a forwarding proxy is written the requests, as if it were the host
+            Some(proxy) if proxy.forwards(url) => {
+                let (stream, resolve) = Self::connect_tcp(&proxy.host, proxy.port, connect_timeout, read_timeout, resolver)?;
+                (stream, resolve, Duration::ZERO)
+            }
+            Some(proxy) => {
+                let (mut stream, resolve) = Self::connect_tcp(&proxy.host, proxy.port, connect_timeout, read_timeout, resolver)?;
+                let start = Instant::now();
+                proxy.tunnel(&mut stream, host, port, resolver)?;
+                (stream, resolve, start.elapsed())
             }
-            Scheme::Https => Self::connect_tls(host, port, connect_tiomeout, read_tiomeout),
+        };
+        match attach {
+            None => Ok(Stream::Plain {
+                stream,
+                resolve,
+                proxy,
+                shaper: None,
+            }),
+            Some(attach) => Self::connect_tls(stream, resolve, proxy, attach),
         }
     }
 
+    #[cfg(unix)]
+    fn connect_unix(path: &str, read_timeout: Option<Duration>) -> Result<Self, Error> {
+        let stream = std::os::unix::net::UnixStream::connect(path).map_err(|_e| Error::UnixConnectFailed)?;
+        stream.set_read_timeout(read_timeout).map_err(|_e| Error::SetReadTimeoutFailed)?;
+        Ok(Stream::Unix { stream, shaper: None })
+    }
+
+    #[cfg(not(unix))]
+    fn connect_unix(_path: &str, _read_timeout: Option<Duration>) -> Result<Self, Error> {
+        Err(Error::UnixConnectFailed)
+    }
+
     fn connect_tcp(
         host: &str,
         port: u16,
-        connect_tiomeout: Option<Duration>,
-        read_tiomeout: Option<Duration>,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+        resolver: &Resolver,
     ) -> Result<(TcpStream, Duration), Error> {
         let resolve_start = Instant::now();
-        let addrs = match (host, port).to_socket_addrs() {
-            Ok(addrs) => Ok(addrs.collect::<Vec<SocketAddr>>()),
-            Err(_) => Err(Error::HostNotFound),
-        }?;
+        let addrs = resolver.resolve(host, port)?;
         let resolve_duration = resolve_start.elapsed();
-        let stream = Self::connect_happy(addrs, connect_tiomeout)?;
-        stream.set_read_timeout(read_tiomeout).map_err(|_e| Error::SetReadTimeoutFailed)?;
+        let stream = Self::connect_happy(addrs, resolver.family, connect_timeout)?;
+        stream.set_read_timeout(read_timeout).map_err(|_e| Error::SetReadTimeoutFailed)?;
         Ok((stream, resolve_duration))
     }
 
-    fn connect_happy(addrs: Vec<SocketAddr>, timeout: Option<Duration>) -> Result<TcpStream, Error> {
+    fn connect_happy(addrs: Vec<SocketAddr>, family: Family, timeout: Option<Duration>) -> Result<TcpStream, Error> {
+        let addrs = addrs
+            .into_iter()
+            .filter(|addr| match family {
+                Family::Any => true,
+                Family::V4 => addr.is_ipv4(),
+                Family::V6 => addr.is_ipv6(),
+            })
+            .collect::<Vec<SocketAddr>>();
+        if addrs.is_empty() {
+            return Err(Error::HostNotFound);
+        }
         if let [addr] = &addrs[..] {
             // debug!("DNS returned only one address, using fast path");
             return Self::connect_timeout(addr, timeout);
@@ -121,30 +290,102 @@ impl Stream {
             Some(timeout) => TcpStream::connect_timeout(addr, timeout),
             None => TcpStream::connect(addr),
         }
-        .map_err(|_e| Error::TcpConnectFailed)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => Error::ConnectTimeout,
+            _ => Error::TcpConnectFailed,
+        })
     }
 
-    fn connect_tls(host: &str, port: u16, connect_tiomeout: Option<Duration>, read_tiomeout: Option<Duration>) -> Result<Self, Error> {
-        use rustls_platform_verifier::BuilderVerifierExt;
-        let config = Arc::new(rustls::ClientConfig::builder().with_platform_verifier().with_no_client_auth());
-        let name = host.to_owned().try_into().map_err(|_e| Error::InvalidUrlHost)?;
-        let attach = Box::new(rustls::ClientConnection::new(config, name).map_err(|_e| Error::TlsHandshakeFailed)?);
-        let (stream, resolve) = Self::connect_tcp(host, port, connect_tiomeout, read_tiomeout)?;
-        Ok(Stream::Cipher { attach, stream, resolve })
+    /// The TLS side of a connection to the host, made before connecting so bad settings fail early.
+    fn attach(host: &str, settings: &Settings, protocols: &[&[u8]]) -> Result<Box<rustls::ClientConnection>, Error> {
+        let mut config = settings.config()?;
+        config.alpn_protocols = protocols.iter().map(|protocol| protocol.to_vec()).collect();
+        let name = settings
+            .sni
+            .as_deref()
+            .unwrap_or(host)
+            .to_owned()
+            .try_into()
+            .map_err(|_e| Error::InvalidUrlHost)?;
+        let attach = rustls::ClientConnection::new(Arc::new(config), name).map_err(|_e| Error::TlsHandshakeFailed)?;
+        Ok(Box::new(attach))
     }
 
-    pub fn resolve(&self) -> Duration {
+    fn connect_tls(
+        mut stream: TcpStream,
+        resolve: Duration,
+        proxy: Duration,
+        mut attach: Box<rustls::ClientConnection>,
+    ) -> Result<Self, Error> {
+        // the handshake completes before the request is written, so the protocol the server chose is known
+        let start = Instant::now();
+        while attach.is_handshaking() {
+            attach.complete_io(&mut stream).map_err(|e| match e.kind() {
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => Error::ConnectTimeout,
+                _ => Error::TlsHandshakeFailed,
+            })?;
+        }
+        let tls = start.elapsed();
+        let session = Box::new(Session::from(&attach));
+        Ok(Stream::Cipher {
+            attach,
+            stream,
+            resolve,
+            proxy,
+            tls,
+            session,
+            shaper: None,
+        })
+    }
+
+    /// Limits how long a read or write waits, a connection from the pool may get a new limit for every request.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        let stream = match self {
+            Stream::Plain { stream, .. } | Stream::Cipher { stream, .. } => stream,
+            #[cfg(unix)]
+            Stream::Unix { stream, .. } => {
+                stream.set_read_timeout(timeout).map_err(|_e| Error::SetReadTimeoutFailed)?;
+                return stream.set_write_timeout(timeout).map_err(|_e| Error::SetReadTimeoutFailed);
+            }
+            #[cfg(test)]
+            Stream::Mock(_) => return Ok(()),
+        };
+        stream.set_read_timeout(timeout).map_err(|_e| Error::SetReadTimeoutFailed)?;
+        stream.set_write_timeout(timeout).map_err(|_e| Error::SetReadTimeoutFailed)
+    }
+
+    /// Holds the reads and writes of the requests to come back to the conditions of the throttle, a connection
+    /// from the pool may get new ones for every request. What was held back before is forgotten.
+    pub fn throttle(&mut self, throttle: Throttle) {
+        if let Some(shaper) = self.shaper() {
+            *shaper = (!throttle.none()).then(|| Box::new(Shaper::new(throttle)));
+        }
+    }
+
+    /// How long reads and writes were held back since the connection was last throttled.
+    pub fn throttled(&self) -> Duration {
         match self {
-            Stream::Plain { resolve, .. } => *resolve,
-            Stream::Cipher { resolve, .. } => *resolve,
+            Stream::Plain { shaper, .. } | Stream::Cipher { shaper, .. } => shaper.as_ref().map(|shaper| shaper.slept()),
+            #[cfg(unix)]
+            Stream::Unix { shaper, .. } => shaper.as_ref().map(|shaper| shaper.slept()),
             #[cfg(test)]
-            Stream::Mock(_) => Duration::default(),
+            Stream::Mock(_) => None,
         }
+        .unwrap_or_default()
     }
-}
 
-impl Read for Stream {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    fn shaper(&mut self) -> Option<&mut Option<Box<Shaper>>> {
+        match self {
+            Stream::Plain { shaper, .. } | Stream::Cipher { shaper, .. } => Some(shaper),
+            #[cfg(unix)]
+            Stream::Unix { shaper, .. } => Some(shaper),
+            #[cfg(test)]
+            Stream::Mock(_) => None,
+        }
+    }
+
+    /// Reads from the connection as fast as it can.
+    fn receive(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
             Stream::Plain { stream, .. } => stream.read(buf),
             Stream::Cipher {
@@ -164,26 +405,134 @@ impl Read for Stream {
                     r => r,
                 }
             }
+            #[cfg(unix)]
+            Stream::Unix { stream, .. } => stream.read(buf),
             #[cfg(test)]
             Stream::Mock(cursor) => cursor.read(buf),
         }
     }
-}
 
-impl Write for Stream {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    /// Writes to the connection as fast as it can.
+    fn send(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         match self {
             Stream::Plain { stream, .. } => stream.write(buf),
             Stream::Cipher { stream, attach, .. } => rustls::Stream::new(attach.as_mut(), stream).write(buf),
+            #[cfg(unix)]
+            Stream::Unix { stream, .. } => stream.write(buf),
             #[cfg(test)]
             Stream::Mock(cursor) => cursor.write(buf),
         }
     }
 
+    /// Whether an idle connection is still open, a closed one reads end of file without blocking.
+    pub fn alive(&self) -> bool {
+        let stream = match self {
+            Stream::Plain { stream, .. } | Stream::Cipher { stream, .. } => stream,
+            #[cfg(unix)]
+            Stream::Unix { stream, .. } => {
+                // a unix socket can not peek, but an idle connection has nothing to read anyway
+                if stream.set_nonblocking(true).is_err() {
+                    return false;
+                }
+                let read = (&*stream).read(&mut [0; 1]);
+                let blocking = stream.set_nonblocking(false).is_ok();
+                return blocking && matches!(read, Err(error) if error.kind() == std::io::ErrorKind::WouldBlock);
+            }
+            #[cfg(test)]
+            Stream::Mock(_) => return true,
+        };
+        if stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let peek = stream.peek(&mut [0; 1]);
+        let blocking = stream.set_nonblocking(false).is_ok();
+        blocking && matches!(peek, Err(error) if error.kind() == std::io::ErrorKind::WouldBlock)
+    }
+
+    /// Closes the writing half of the connection, telling the server the request is over.
+    pub fn shutdown(&self) -> Result<(), Error> {
+        match self {
+            Stream::Plain { stream, .. } | Stream::Cipher { stream, .. } => stream.shutdown(std::net::Shutdown::Write),
+            #[cfg(unix)]
+            Stream::Unix { stream, .. } => stream.shutdown(std::net::Shutdown::Write),
+            #[cfg(test)]
+            Stream::Mock(_) => Ok(()),
+        }
+        .map_err(Error::WriteFailed)
+    }
+
+    /// Whether the server chose HTTP/2 over ALPN in the TLS handshake.
+    pub fn multiplexed(&self) -> bool {
+        matches!(self, Stream::Cipher { attach, .. } if attach.alpn_protocol() == Some(b"h2"))
+    }
+
+    pub fn tls(&self) -> Duration {
+        match self {
+            Stream::Cipher { tls, .. } => *tls,
+            _ => Duration::default(),
+        }
+    }
+
+    /// What the TLS handshake agreed on, none for a plain connection.
+    pub fn session(&self) -> Option<&Session> {
+        match self {
+            Stream::Cipher { session, .. } => Some(session),
+            _ => None,
+        }
+    }
+
+    pub fn proxy(&self) -> Duration {
+        match self {
+            Stream::Plain { proxy, .. } | Stream::Cipher { proxy, .. } => *proxy,
+            _ => Duration::default(),
+        }
+    }
+
+    pub fn resolve(&self) -> Duration {
+        match self {
+            Stream::Plain { resolve, .. } => *resolve,
+            Stream::Cipher { resolve, .. } => *resolve,
+            _ => Duration::default(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // the shaper is taken out while the connection is read, and put back after
+        let Some(mut shaper) = self.shaper().and_then(Option::take) else {
+            return self.receive(buf);
+        };
+        let length = shaper.before(buf.len(), false);
+        let result = self.receive(&mut buf[..length]);
+        if let Ok(read) = result {
+            shaper.after(read, false);
+        }
+        *self.shaper().unwrap_or(&mut None) = Some(shaper);
+        result
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let Some(mut shaper) = self.shaper().and_then(Option::take) else {
+            return self.send(buf);
+        };
+        let length = shaper.before(buf.len(), true);
+        let result = self.send(&buf[..length]);
+        if let Ok(written) = result {
+            shaper.after(written, true);
+        }
+        *self.shaper().unwrap_or(&mut None) = Some(shaper);
+        result
+    }
+
     fn flush(&mut self) -> std::io::Result<()> {
         match self {
             Stream::Plain { stream, .. } => stream.flush(),
             Stream::Cipher { stream, attach, .. } => rustls::Stream::new(attach.as_mut(), stream).flush(),
+            #[cfg(unix)]
+            Stream::Unix { stream, .. } => stream.flush(),
             #[cfg(test)]
             Stream::Mock(cursor) => cursor.flush(),
         }
@@ -227,3 +576,20 @@ fn test_rotate_right() {
     let x: Vec<u32> = rotate(vec![1, 2, 3].into_iter(), vec![4, 5, 6, 100, 101].into_iter()).collect();
     assert_eq!(&x[..], &[1, 4, 2, 5, 3, 6, 100, 101][..]);
 }
+
+#[test]
+fn test_resolver_parse() {
+    let ipv4 = "127.0.0.1".parse::<IpAddr>().unwrap();
+    let ipv6 = "::1".parse::<IpAddr>().unwrap();
+    assert_eq!(
+        Resolver::parse("Example.com:443:127.0.0.1"),
+        Some((String::from("example.com"), 443, vec![ipv4]))
+    );
+    assert_eq!(
+        Resolver::parse("example.com:80:127.0.0.1,[::1]"),
+        Some((String::from("example.com"), 80, vec![ipv4, ipv6]))
+    );
+    assert_eq!(Resolver::parse("example.com:http:127.0.0.1"), None);
+    assert_eq!(Resolver::parse("example.com:80:localhost"), None);
+    assert_eq!(Resolver::parse("example.com"), None);
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/// Network conditions a connection simulates, like those of a slow mobile client.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Throttle {
+    /// Bytes a second written at most
+    pub upload: Option<u64>,
+    /// Bytes a second read at most
+    pub download: Option<u64>,
+    /// Waited before every read and write
+    pub latency: Option<Duration>,
+}
+
+impl Throttle {
+    /// Whether the connection is left as it is.
+    pub fn none(&self) -> bool {
+        *self == Throttle::default()
+    }
+}
+
+/// Holds the reads and writes of a connection back to the conditions of a throttle.
+pub struct Shaper {
+    throttle: Throttle,
+    upload: Pace,
+    download: Pace,
+    /// How long reads and writes were held back
+    slept: Duration,
+}
+
+impl Shaper {
+    pub fn new(throttle: Throttle) -> Shaper {
+        Shaper {
+            throttle,
+            upload: Pace::default(),
+            download: Pace::default(),
+            slept: Duration::ZERO,
+        }
+    }
+
+    pub fn slept(&self) -> Duration {
+        self.slept
+    }
+
+    /// Waits out the latency before a read or write of at most this many bytes, returns how many it may move.
+    pub fn before(&mut self, length: usize, write: bool) -> usize {
+        if let Some(latency) = self.throttle.latency {
+            self.sleep(latency);
+        }
+        let rate = match write {
+            true => self.throttle.upload,
+            false => self.throttle.download,
+        };
+        // a tenth of a second of bytes at a time, so the pace is even
+        rate.map_or(length, |rate| length.min((rate / 10).max(1) as usize))
+    }
+
+    /// Waits until the bytes moved so far are within the rate.
+    pub fn after(&mut self, moved: usize, write: bool) {
+        let (pace, rate) = match write {
+            true => (&mut self.upload, self.throttle.upload),
+            false => (&mut self.download, self.throttle.download),
+        };
+        if let Some(rate) = rate.filter(|rate| *rate > 0) {
+            let wait = pace.wait(rate, moved);
+            self.sleep(wait);
+        }
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        if !duration.is_zero() {
+            std::thread::sleep(duration);
+            self.slept += duration;
+        }
+    }
+}
+
+/// Bytes moved in one direction since the first of them.
+#[derive(Default)]
+struct Pace {
+    start: Option<Instant>,
+    bytes: u64,
+}
+
+impl Pace {
+    /// How long to wait until these bytes too are within the rate.
+    fn wait(&mut self, rate: u64, moved: usize) -> Duration {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        self.bytes += moved as u64;
+        Duration::from_secs_f64(self.bytes as f64 / rate as f64).saturating_sub(start.elapsed())
+    }
+}
+
+#[test]
+fn test_shaper_pace() {
+    let mut shaper = Shaper::new(Throttle {
+        upload: Some(1000),
+        download: None,
+        latency: Some(Duration::from_millis(10)),
+    });
+    let start = Instant::now();
+    let mut written = 0;
+    while written < 300 {
+        let length = shaper.before(1000 - written, true);
+        assert!(length <= 100);
+        written += length;
+        shaper.after(length, true);
+    }
+    // three hundred bytes at a thousand a second, the latencies count toward the pace after the first bytes
+    assert!(start.elapsed() >= Duration::from_millis(310));
+    assert!(shaper.slept() >= Duration::from_millis(300));
+    assert_eq!(shaper.before(1000, false), 1000);
+}
@@ -7,9 +7,15 @@ pub struct Time {
     pub total: Duration,
     pub resolve: Duration,
     pub connect: Duration,
+    /// The tunnels opened through proxies, after connecting to them
+    pub proxy: Duration,
+    /// The TLS handshakes of the connections opened
+    pub tls: Duration,
     pub write: Duration,
     pub delay: Duration,
     pub read: Duration,
+    /// How long throttling held reads and writes back, a part of the phases it slowed
+    pub throttle: Duration,
 }
 
 impl Default for Time {
@@ -21,9 +27,22 @@ impl Default for Time {
             total: Duration::ZERO,
             resolve: Duration::ZERO,
             connect: Duration::ZERO,
+            proxy: Duration::ZERO,
+            tls: Duration::ZERO,
             write: Duration::ZERO,
             delay: Duration::ZERO,
             read: Duration::ZERO,
+            throttle: Duration::ZERO,
         }
     }
 }
+
+/// Days from the epoch to a date of the proleptic gregorian calendar.
+pub(super) fn days(year: u64, month: u64, day: u64) -> i64 {
+    let (y, m) = if month <= 2 {
+        (year as i64 - 1, month as i64 + 9)
+    } else {
+        (year as i64, month as i64 - 3)
+    };
+    365 * y + y / 4 - y / 100 + y / 400 + (153 * m + 2) / 5 + day as i64 - 1 - 719468
+}
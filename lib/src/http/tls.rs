@@ -0,0 +1,290 @@
+use super::Error;
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::client::danger::ServerCertVerified;
+use rustls::client::danger::ServerCertVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::PrivateKeyDer;
+use rustls::pki_types::ServerName;
+use rustls::pki_types::UnixTime;
+use rustls::DigitallySignedStruct;
+use rustls::SignatureScheme;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How connections are secured, beyond what the platform trusts.
+#[derive(Clone, Default, Debug, Hash, PartialEq, Eq)]
+pub struct Settings {
+    /// PEM files of roots trusted along with those of the platform
+    pub roots: Vec<PathBuf>,
+    /// PEM files of the client certificate chain and its private key, the key may be in the certificate file
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// Whether the certificate of the server is accepted without verifying it
+    pub insecure: bool,
+    /// The name sent in the handshake and verified against the certificate instead of the host
+    pub sni: Option<String>,
+    /// SHA-256 fingerprints in hex, the certificate of the server must have one of them
+    pub pins: Vec<String>,
+}
+
+impl Settings {
+    /// Builds the config of a connection, files are read every time so a changed one is picked up.
+    pub fn config(&self) -> Result<rustls::ClientConfig, Error> {
+        let builder = rustls::ClientConfig::builder();
+        let provider = builder.crypto_provider().clone();
+        let mut roots = Vec::new();
+        for path in self.roots.iter() {
+            roots.extend(certificates(path)?);
+        }
+        let platform = rustls_platform_verifier::Verifier::new_with_extra_roots(roots)
+            .map_err(|_e| Error::InvalidCertificate)?
+            .with_provider(provider.clone());
+        let verifier: Arc<dyn ServerCertVerifier> = match (self.insecure, self.pins.is_empty()) {
+            (false, true) => Arc::new(platform),
+            (insecure, _) => Arc::new(Verifier {
+                inner: (!insecure).then(|| Arc::new(platform) as Arc<dyn ServerCertVerifier>),
+                pins: self.pins.iter().map(|pin| pin.replace(':', "").to_ascii_lowercase()).collect(),
+                provider,
+            }),
+        };
+        let builder = builder.dangerous().with_custom_certificate_verifier(verifier);
+        match &self.cert {
+            Some(cert) => {
+                let key = PrivateKeyDer::from_pem_file(self.key.as_ref().unwrap_or(cert)).map_err(|_e| Error::InvalidPrivateKey)?;
+                builder
+                    .with_client_auth_cert(certificates(cert)?, key)
+                    .map_err(|_e| Error::InvalidPrivateKey)
+            }
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+}
+
+/// Reads every certificate of a PEM file.
+fn certificates(path: &PathBuf) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<CertificateDer>, _>>())
+        .map_err(|_e| Error::InvalidCertificate)?;
+    match certificates.is_empty() {
+        true => Err(Error::InvalidCertificate),
+        false => Ok(certificates),
+    }
+}
+
+/// Checks the certificate of the server against pinned fingerprints, after the platform verified it unless insecure.
+#[derive(Debug)]
+struct Verifier {
+    inner: Option<Arc<dyn ServerCertVerifier>>,
+    pins: Vec<String>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(inner) = &self.inner {
+            inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+        if !self.pins.is_empty() && !self.pins.contains(&fingerprint(end_entity)) {
+            return Err(rustls::Error::General(String::from("certificate pin mismatch")));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// The SHA-256 fingerprint of a certificate in lowercase hex.
+fn fingerprint(certificate: &CertificateDer<'_>) -> String {
+    ring::digest::digest(&ring::digest::SHA256, certificate.as_ref())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// What the TLS handshake of a connection agreed on, and the certificate the server showed.
+#[derive(Clone, Default)]
+pub struct Session {
+    /// The protocol version, like `TLSv1.3`
+    pub version: String,
+    /// The cipher suite, like `TLS13_AES_128_GCM_SHA256`
+    pub cipher: String,
+    /// The protocol chosen over ALPN, empty if the server chose none
+    pub alpn: String,
+    /// The subject of the peer certificate, like `CN=example.com, O=Example`
+    pub subject: String,
+    /// When the peer certificate expires, since the unix epoch
+    pub expires: Duration,
+}
+
+impl Session {
+    pub fn from(connection: &rustls::ClientConnection) -> Session {
+        let version = match connection.protocol_version() {
+            Some(rustls::ProtocolVersion::TLSv1_3) => String::from("TLSv1.3"),
+            Some(rustls::ProtocolVersion::TLSv1_2) => String::from("TLSv1.2"),
+            Some(version) => format!("{:?}", version),
+            None => String::new(),
+        };
+        let cipher = connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .unwrap_or_default();
+        let alpn = connection
+            .alpn_protocol()
+            .map(|alpn| String::from_utf8_lossy(alpn).into_owned())
+            .unwrap_or_default();
+        let (subject, expires) = connection
+            .peer_certificates()
+            .and_then(|certificates| certificates.first())
+            .and_then(|certificate| certificate_of(certificate.as_ref()))
+            .unwrap_or_default();
+        Session {
+            version,
+            cipher,
+            alpn,
+            subject,
+            expires,
+        }
+    }
+}
+
+/// Reads a DER element, returns its tag, its contents and what follows it.
+fn element(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x84 => {
+            let (length, rest) = rest.split_at_checked((first & 0x7f) as usize)?;
+            (length.iter().fold(0, |length, byte| length << 8 | *byte as usize), rest)
+        }
+        _ => return None,
+    };
+    let (contents, rest) = rest.split_at_checked(length)?;
+    Some((tag, contents, rest))
+}
+
+/// The subject and expiry of an X.509 certificate.
+fn certificate_of(der: &[u8]) -> Option<(String, Duration)> {
+    let (_, certificate, _) = element(der)?;
+    let (_, mut fields, _) = element(certificate)?;
+    if let (0xa0, _, rest) = element(fields)? {
+        // the version is only there when it is not the first one
+        fields = rest;
+    }
+    // the serial number, the signature algorithm and the issuer come before the validity
+    for _ in 0..3 {
+        fields = element(fields)?.2;
+    }
+    let (_, validity, rest) = element(fields)?;
+    let (_, subject, _) = element(rest)?;
+    let (_, _, after) = element(validity)?;
+    let (tag, expires, _) = element(after)?;
+    Some((name(subject), time(tag, expires)?))
+}
+
+/// Formats the attributes of a distinguished name, those without a short name are left out.
+fn name(mut sets: &[u8]) -> String {
+    let mut attributes = Vec::new();
+    while let Some((_, set, rest)) = element(sets) {
+        sets = rest;
+        let Some((_, attribute, _)) = element(set) else { continue };
+        let Some((_, oid, rest)) = element(attribute) else { continue };
+        let Some((_, value, _)) = element(rest) else { continue };
+        let short = match oid {
+            [0x55, 0x04, 0x03] => "CN",
+            [0x55, 0x04, 0x06] => "C",
+            [0x55, 0x04, 0x07] => "L",
+            [0x55, 0x04, 0x08] => "ST",
+            [0x55, 0x04, 0x0a] => "O",
+            [0x55, 0x04, 0x0b] => "OU",
+            _ => continue,
+        };
+        attributes.push(format!("{}={}", short, String::from_utf8_lossy(value)));
+    }
+    attributes.join(", ")
+}
+
+/// Parses an UTCTime like `250101120000Z` or a GeneralizedTime like `20500101120000Z`.
+fn time(tag: u8, text: &[u8]) -> Option<Duration> {
+    let text = std::str::from_utf8(text).ok()?.strip_suffix('Z')?;
+    let (year, text) = match tag {
+        0x17 => {
+            let year = text.get(..2)?.parse::<u64>().ok()?;
+            (if year < 50 { year + 2000 } else { year + 1900 }, &text[2..])
+        }
+        0x18 => (text.get(..4)?.parse::<u64>().ok()?, &text[4..]),
+        _ => return None,
+    };
+    let digits = |range: std::ops::Range<usize>| text.get(range)?.parse::<u64>().ok();
+    let (month, day) = (digits(0..2)?, digits(2..4)?);
+    let seconds = digits(4..6)? * 3600 + digits(6..8)? * 60 + digits(8..10)?;
+    let days = super::time::days(year, month, day);
+    Some(Duration::from_secs(u64::try_from(days * 86400).ok()? + seconds))
+}
+
+#[test]
+fn test_tls_certificate() {
+    let der = |tag: u8, contents: &[u8]| {
+        let mut bytes = vec![tag];
+        match contents.len() {
+            length @ 0..=0x7f => bytes.push(length as u8),
+            length => bytes.extend_from_slice(&[0x82, (length >> 8) as u8, length as u8]),
+        }
+        bytes.extend_from_slice(contents);
+        bytes
+    };
+    let attribute = |oid: u8, value: &str| {
+        der(
+            0x31,
+            &der(0x30, &[der(0x06, &[0x55, 0x04, oid]), der(0x0c, value.as_bytes())].concat()),
+        )
+    };
+    let subject = der(0x30, &[attribute(0x03, "example.com"), attribute(0x0a, "Example")].concat());
+    let validity = der(0x30, &[der(0x17, b"250101000000Z"), der(0x18, b"20500101120000Z")].concat());
+    let fields = [
+        der(0xa0, &der(0x02, &[2])),
+        der(0x02, &[1]),
+        der(0x30, &[]),
+        der(0x30, &[]),
+        validity,
+        subject,
+        der(0x30, &[0; 200]),
+    ]
+    .concat();
+    let certificate = der(0x30, &[der(0x30, &fields), der(0x30, &[]), der(0x03, &[0])].concat());
+    let (subject, expires) = certificate_of(&certificate).unwrap();
+    assert_eq!(subject, "CN=example.com, O=Example");
+    assert_eq!(expires.as_secs(), 2524651200);
+    assert_eq!(certificate_of(&certificate[..10]), None);
+}
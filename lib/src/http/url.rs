@@ -1,49 +1,137 @@
+use super::Error;
 use super::Scheme;
 
+/// An RFC 3986 url, the userinfo is kept apart and sent as basic authorization.
+/// The host of an `http+unix` url is the percent-encoded path of its socket, like `%2Fvar%2Frun%2Fdocker.sock`.
 pub struct Url {
     pub scheme: Scheme,
+    /// The user of the userinfo, percent-decoded
+    pub username: String,
+    /// The password of the userinfo, percent-decoded
+    pub password: Option<String>,
+    /// The host, an IPv6 literal without its brackets
     pub host: String,
     pub port: u16,
     pub path: String,
+    pub query: Option<String>,
+    /// The fragment, never sent to the server
+    pub fragment: Option<String>,
 }
 
-impl std::convert::From<&str> for Url {
-    fn from(str: &str) -> Self {
-        let mut splits = str.split("://");
-        let scheme = Scheme::from(splits.next());
-        let (host, port, path) = match splits.next() {
-            Some(str) => {
-                let (host, path) = if let Some(i) = str.find('/') {
-                    (&str[..i], &str[i..])
-                } else {
-                    (str, "/")
-                };
-                let (host, port) = if let Some(j) = host.find(':') {
-                    (&host[..j], host[j + 1..].parse::<u16>().ok())
-                } else {
-                    (host, None)
-                };
-                (host.to_string(), port, path.to_string())
-            }
-            None => ("localhost".to_string(), None, "/".to_string()),
+impl Url {
+    /// The host with the port when it is not the default of the scheme.
+    pub fn authority(&self) -> String {
+        let host = match self.host.contains(':') {
+            true => format!("[{}]", self.host),
+            false => self.host.to_owned(),
         };
-        let port = match port {
-            Some(port) => port,
-            None => match scheme {
-                Scheme::Http => 80,
-                Scheme::Https => 443,
-            },
+        match (self.scheme, self.port) {
+            (Scheme::Http | Scheme::Ws, 80) | (Scheme::Https | Scheme::Wss, 443) | (Scheme::HttpUnix, _) => host,
+            (_, port) => format!("{}:{}", host, port),
+        }
+    }
+
+    /// The path of the socket an `http+unix` url connects to.
+    pub fn socket(&self) -> String {
+        decode(&self.host)
+    }
+
+    /// The path and query sent in the request line.
+    pub fn target(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.to_owned(),
+        }
+    }
+
+    /// Resolves a reference, like the location of a redirect, against this url.
+    pub fn join(&self, reference: &str) -> Result<Url, Error> {
+        if reference.contains("://") {
+            return Url::try_from(reference);
+        }
+        if let Some(reference) = reference.strip_prefix("//") {
+            return Url::try_from(format!("{}://{}", self.scheme, reference).as_str());
+        }
+        let (reference, fragment) = split(reference, '#');
+        let (path, query) = split(reference, '?');
+        let (path, query) = match path {
+            "" => (self.path.to_owned(), query.or(self.query.as_deref())),
+            path if path.starts_with('/') => (normalize(path), query),
+            path => (
+                normalize(&format!("{}{}", &self.path[..self.path.rfind('/').map_or(0, |i| i + 1)], path)),
+                query,
+            ),
         };
-        Url { scheme, host, port, path }
+        Ok(Url {
+            scheme: self.scheme,
+            username: self.username.to_owned(),
+            password: self.password.to_owned(),
+            host: self.host.to_owned(),
+            port: self.port,
+            path,
+            query: query.map(str::to_owned),
+            fragment: fragment.map(str::to_owned),
+        })
     }
 }
 
-impl std::convert::From<Option<&str>> for Url {
-    fn from(str: Option<&str>) -> Self {
-        match str {
-            Some(str) => Self::from(str),
-            None => Self::default(),
-        }
+impl std::convert::TryFrom<&str> for Url {
+    type Error = Error;
+
+    /// Parses a url, a scheme that is not known or a port that is not a number up to 65535 is an error
+    /// rather than a guess.
+    fn try_from(str: &str) -> Result<Self, Self::Error> {
+        let (scheme, rest) = match str.split_once("://") {
+            Some((name, rest)) => {
+                let name = name.to_ascii_lowercase();
+                let scheme = Scheme::from(name.as_str());
+                if scheme.as_ref() != name {
+                    return Err(Error::InvalidUrlScheme);
+                }
+                (scheme, rest)
+            }
+            None => (Scheme::default(), str),
+        };
+        let (rest, fragment) = split(rest, '#');
+        let (rest, query) = split(rest, '?');
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (userinfo, host) = match authority.rsplit_once('@') {
+            Some((userinfo, host)) => (Some(userinfo), host),
+            None => (None, authority),
+        };
+        let (username, password) = match userinfo.map(|userinfo| split(userinfo, ':')) {
+            Some((username, password)) => (decode(username), password.map(decode)),
+            None => (String::default(), None),
+        };
+        let (host, port) = match host.strip_prefix('[').and_then(|host| host.split_once(']')) {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => split(host, ':'),
+        };
+        let host = match host {
+            "" => String::from("localhost"),
+            host => host.to_owned(),
+        };
+        let port = match port.filter(|port| !port.is_empty()) {
+            Some(port) => port.parse::<u16>().map_err(|_| Error::InvalidUrlPort)?,
+            None => match scheme {
+                Scheme::Http | Scheme::Ws => 80,
+                Scheme::Https | Scheme::Wss => 443,
+                Scheme::HttpUnix | Scheme::Tcp => 0,
+            },
+        };
+        Ok(Url {
+            scheme,
+            username,
+            password,
+            host,
+            port,
+            path: path.to_owned(),
+            query: query.map(str::to_owned),
+            fragment: fragment.map(str::to_owned),
+        })
     }
 }
 
@@ -51,23 +139,105 @@ impl Default for Url {
     fn default() -> Self {
         Self {
             scheme: Scheme::default(),
+            username: String::default(),
+            password: None,
             host: "localhost".to_string(),
             port: 80,
             path: "/".to_string(),
+            query: None,
+            fragment: None,
         }
     }
 }
 
+/// Shows the url without its userinfo.
 impl std::fmt::Display for Url {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}://{}{}", self.scheme, self.host, self.path)
+        write!(f, "{}://{}{}", self.scheme, self.authority(), self.target())?;
+        match &self.fragment {
+            Some(fragment) => write!(f, "#{}", fragment),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Splits at the first delimiter, the part after it is none without one.
+fn split(str: &str, delimiter: char) -> (&str, Option<&str>) {
+    match str.split_once(delimiter) {
+        Some((left, right)) => (left, Some(right)),
+        None => (str, None),
     }
 }
 
+/// Removes the `.` and `..` segments of a path.
+fn normalize(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').skip(1).peekable();
+    while let Some(part) = parts.next() {
+        match part {
+            "." | ".." => {
+                if part == ".." {
+                    segments.pop();
+                }
+                // a trailing dot segment leaves the path ending with a slash
+                if parts.peek().is_none() {
+                    segments.push("");
+                }
+            }
+            part => segments.push(part),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+fn decode(str: &str) -> String {
+    let bytes = str.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], str.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Percent-encodes a value interpolated into a message after the text before it.
+/// Values in the query of the request line are encoded as a component,
+/// values before it only lose what a url can not hold, so they may carry slashes.
+/// Values in headers or the body are kept as they are.
+pub fn escape(before: &str, value: &str) -> String {
+    let line = before.trim_start();
+    if line.contains('\n') {
+        return value.to_owned();
+    }
+    let query = line.contains('?');
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        let keep = match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => true,
+            b'!' | b'$' | b'%' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' | b':' | b'@' | b'/' | b'?' => !query,
+            _ => false,
+        };
+        match keep {
+            true => escaped.push(byte as char),
+            false => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
 #[test]
 fn test_url_with_ip() {
     let str = "http://IP_ADDRESS";
-    let url = Url::from(str);
+    let url = Url::try_from(str).unwrap();
     assert_eq!(url.scheme, Scheme::Http);
     assert_eq!(url.host, "IP_ADDRESS");
     assert_eq!(url.port, 80);
@@ -77,7 +247,7 @@ fn test_url_with_ip() {
 #[test]
 fn test_url_with_ip_port() {
     let str = "http://IP_ADDRESS:52831";
-    let url = Url::from(str);
+    let url = Url::try_from(str).unwrap();
     assert_eq!(url.scheme, Scheme::Http);
     assert_eq!(url.host, "IP_ADDRESS");
     assert_eq!(url.port, 52831);
@@ -87,7 +257,7 @@ fn test_url_with_ip_port() {
 #[test]
 fn test_url_with_ip_path() {
     let str = "http://IP_ADDRESS/hello";
-    let url = Url::from(str);
+    let url = Url::try_from(str).unwrap();
     assert_eq!(url.scheme, Scheme::Http);
     assert_eq!(url.host, "IP_ADDRESS");
     assert_eq!(url.port, 80);
@@ -97,7 +267,7 @@ fn test_url_with_ip_path() {
 #[test]
 fn test_url_with_ip_port_path() {
     let str = "http://IP_ADDRESS:52831/hello/world";
-    let url = Url::from(str);
+    let url = Url::try_from(str).unwrap();
     assert_eq!(url.scheme, Scheme::Http);
     assert_eq!(url.host, "IP_ADDRESS");
     assert_eq!(url.port, 52831);
@@ -107,39 +277,112 @@ fn test_url_with_ip_port_path() {
 #[test]
 fn test_url_with_ip_path_query() {
     let str = "http://IP_ADDRESS/hello/world?a=b";
-    let url = Url::from(str);
+    let url = Url::try_from(str).unwrap();
     assert_eq!(url.scheme, Scheme::Http);
     assert_eq!(url.host, "IP_ADDRESS");
     assert_eq!(url.port, 80);
-    assert_eq!(url.path, "/hello/world?a=b");
+    assert_eq!(url.path, "/hello/world");
+    assert_eq!(url.query.as_deref(), Some("a=b"));
 }
 
 #[test]
 fn test_url_with_ip_port_path_query() {
     let str = "http://IP_ADDRESS:52831/hello/world?a=b";
-    let url = Url::from(str);
+    let url = Url::try_from(str).unwrap();
     assert_eq!(url.scheme, Scheme::Http);
     assert_eq!(url.host, "IP_ADDRESS");
     assert_eq!(url.port, 52831);
-    assert_eq!(url.path, "/hello/world?a=b");
+    assert_eq!(url.path, "/hello/world");
+    assert_eq!(url.query.as_deref(), Some("a=b"));
 }
 
 #[test]
 fn test_url_with_ip_path_query_fragment() {
     let str = "http://IP_ADDRESS/hello/world?a=b#fragment";
-    let url = Url::from(str);
+    let url = Url::try_from(str).unwrap();
     assert_eq!(url.scheme, Scheme::Http);
     assert_eq!(url.host, "IP_ADDRESS");
     assert_eq!(url.port, 80);
-    assert_eq!(url.path, "/hello/world?a=b#fragment");
+    assert_eq!(url.path, "/hello/world");
+    assert_eq!(url.query.as_deref(), Some("a=b"));
+    assert_eq!(url.fragment.as_deref(), Some("fragment"));
 }
 
 #[test]
 fn test_url_with_ip_port_path_query_fragment() {
     let str = "http://IP_ADDRESS:52831/hello/world?a=b#fragment";
-    let url = Url::from(str);
+    let url = Url::try_from(str).unwrap();
     assert_eq!(url.scheme, Scheme::Http);
     assert_eq!(url.host, "IP_ADDRESS");
     assert_eq!(url.port, 52831);
-    assert_eq!(url.path, "/hello/world?a=b#fragment");
+    assert_eq!(url.path, "/hello/world");
+    assert_eq!(url.query.as_deref(), Some("a=b"));
+    assert_eq!(url.fragment.as_deref(), Some("fragment"));
+}
+
+#[test]
+fn test_url_join() {
+    let url = Url::try_from("http://IP_ADDRESS:52831/hello/world?a=b").unwrap();
+    assert_eq!(url.join("https://example.com/").unwrap().to_string(), "https://example.com/");
+    assert_eq!(url.join("//example.com/path").unwrap().to_string(), "http://example.com/path");
+    assert_eq!(url.join("/root").unwrap().to_string(), "http://IP_ADDRESS:52831/root");
+    assert_eq!(
+        url.join("there?c=d").unwrap().to_string(),
+        "http://IP_ADDRESS:52831/hello/there?c=d"
+    );
+    assert_eq!(url.join("?c=d").unwrap().to_string(), "http://IP_ADDRESS:52831/hello/world?c=d");
+    assert_eq!(
+        url.join("../up/./here#top").unwrap().to_string(),
+        "http://IP_ADDRESS:52831/up/here#top"
+    );
+    assert_eq!(url.join("").unwrap().to_string(), "http://IP_ADDRESS:52831/hello/world?a=b");
+}
+
+#[test]
+fn test_url_with_socket() {
+    let url = Url::try_from("http+unix://%2Fvar%2Frun%2Fdocker.sock/v1.43/info?a=b").unwrap();
+    assert_eq!(url.scheme, Scheme::HttpUnix);
+    assert_eq!(
+        (url.socket().as_str(), url.target().as_str()),
+        ("/var/run/docker.sock", "/v1.43/info?a=b")
+    );
+    assert_eq!(url.to_string(), "http+unix://%2Fvar%2Frun%2Fdocker.sock/v1.43/info?a=b");
+    let url = Url::try_from("tcp://127.0.0.1:6379").unwrap();
+    assert_eq!((url.scheme, url.host.as_str(), url.port), (Scheme::Tcp, "127.0.0.1", 6379));
+}
+
+#[test]
+fn test_url_with_ipv6_userinfo() {
+    let url = Url::try_from("HTTP://us%40er:p%3Ass@[::1]:8080?a=b#c").unwrap();
+    assert_eq!(url.scheme, Scheme::Http);
+    assert_eq!((url.username.as_str(), url.password.as_deref()), ("us@er", Some("p:ss")));
+    assert_eq!((url.host.as_str(), url.port), ("::1", 8080));
+    assert_eq!((url.path.as_str(), url.target().as_str()), ("/", "/?a=b"));
+    assert_eq!(url.to_string(), "http://[::1]:8080/?a=b#c");
+    let url = Url::try_from("https://user@example.com:443/").unwrap();
+    assert_eq!((url.username.as_str(), url.password.as_deref()), ("user", None));
+    assert_eq!(url.to_string(), "https://example.com/");
+}
+
+#[test]
+fn test_url_escape() {
+    assert_eq!(escape("\n    GET http://host/", "a b/ü?x=1"), "a%20b/%C3%BC?x=1");
+    assert_eq!(escape("GET http://host/search?q=", "a b&c=d/"), "a%20b%26c%3Dd%2F");
+    assert_eq!(escape("GET http://host/\nX-Name: ", "a b"), "a b");
+}
+
+#[test]
+fn test_url_with_invalid_port() {
+    for str in ["http://IP_ADDRESS:99999/", "IP_ADDRESS:abc", "tcp://[::1]:-1"] {
+        assert!(matches!(Url::try_from(str), Err(Error::InvalidUrlPort)));
+    }
+    assert_eq!(Url::try_from("http://IP_ADDRESS:/").unwrap().port, 80);
+}
+
+#[test]
+fn test_url_with_unknown_scheme() {
+    for str in ["ftp://host/x", "htps://host/x", "://host/x"] {
+        assert!(matches!(Url::try_from(str), Err(Error::InvalidUrlScheme)));
+    }
+    assert!(Url::try_from("WSS://host/x").unwrap().scheme == Scheme::Wss);
 }
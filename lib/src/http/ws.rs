@@ -0,0 +1,306 @@
+use super::Error;
+use super::Headers;
+use super::Request;
+use super::Response;
+use super::Stream;
+use super::Time;
+use super::Url;
+use base64::Engine;
+use ring::rand::SecureRandom;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+/// What a server appends to the key before hashing it into the accept value.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message received, a bigger one fails the connection.
+const LIMIT: usize = 64 << 20;
+
+/// A random value of the `Sec-WebSocket-Key` header.
+pub fn key() -> String {
+    base64::engine::general_purpose::STANDARD.encode(random::<16>())
+}
+
+/// The `Sec-WebSocket-Accept` value a server answers the key with.
+pub fn accept(key: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, format!("{}{}", key, GUID).as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest.as_ref())
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    let _ = ring::rand::SystemRandom::new().fill(&mut bytes);
+    bytes
+}
+
+/// A WebSocket connection, what a `101 Switching Protocols` response upgraded its connection to.
+/// Every message sent or received comes back like a request and response, so it can be recorded.
+pub struct Socket {
+    reader: BufReader<Stream>,
+    url: String,
+    /// Whether this side sent a close frame
+    closing: bool,
+    /// Whether the closing handshake is over or the connection failed
+    closed: bool,
+}
+
+impl Socket {
+    pub(super) fn new(reader: BufReader<Stream>, url: &Url) -> Socket {
+        Socket {
+            reader,
+            url: url.to_string(),
+            closing: false,
+            closed: false,
+        }
+    }
+
+    /// Whether no more messages can be sent or received.
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Sends a text or binary message in one frame.
+    pub fn send(&mut self, payload: &[u8], text: bool) -> (Request, Response, Time, Option<Error>) {
+        let mut time = Time::default();
+        let start = Instant::now();
+        let result = self.write(if text { 0x1 } else { 0x2 }, payload);
+        time.write = start.elapsed();
+        let total = time.write;
+        (self.request(payload), Response::default(), self.finish(time, total), result.err())
+    }
+
+    /// Waits for the next message, answering pings on the way. The response has the kind of the message as its reason,
+    /// `text`, `binary` or `close`, and the code of a close frame as its status.
+    pub fn receive(&mut self, timeout: Option<Duration>) -> (Request, Response, Time, Option<Error>) {
+        let mut time = Time::default();
+        let start = Instant::now();
+        let result = self.read(timeout, &mut time.delay);
+        let total = start.elapsed();
+        time.read = total - time.delay;
+        self.respond(result, time, total)
+    }
+
+    /// Sends a close frame with the code and waits for the one of the server, messages before it are dropped.
+    pub fn close(&mut self, code: u16, timeout: Option<Duration>) -> (Request, Response, Time, Option<Error>) {
+        let mut time = Time::default();
+        let start = Instant::now();
+        let result = self.write(0x8, &code.to_be_bytes()).and_then(|_| {
+            self.closing = true;
+            time.write = start.elapsed();
+            let mut delay = Duration::ZERO;
+            loop {
+                let response = self.read(timeout, &mut delay)?;
+                if response.reason == "close" {
+                    break Ok(response);
+                }
+            }
+        });
+        let total = start.elapsed();
+        time.read = total - time.write;
+        self.respond(result, time, total)
+    }
+
+    fn respond(&self, result: Result<Response, Error>, time: Time, total: Duration) -> (Request, Response, Time, Option<Error>) {
+        let request = self.request(&[]);
+        let time = self.finish(time, total);
+        match result {
+            Ok(response) => (request, response, time, None),
+            Err(error) => (request, Response::default(), time, Some(error)),
+        }
+    }
+
+    fn request(&self, payload: &[u8]) -> Request {
+        Request {
+            url: Url::try_from(self.url.as_str()).unwrap_or_default(),
+            body: String::from_utf8_lossy(payload).into_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn finish(&self, mut time: Time, total: Duration) -> Time {
+        time.total = total;
+        time.end = SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        time
+    }
+
+    /// Reads the frames of the next message, the wait for its first byte is the delay.
+    fn read(&mut self, timeout: Option<Duration>, delay: &mut Duration) -> Result<Response, Error> {
+        if self.closed {
+            return Err(Error::ConnectionClosed);
+        }
+        self.reader.get_ref().set_timeout(timeout)?;
+        let start = Instant::now();
+        self.reader.fill_buf().map_err(Error::ReadFailed)?;
+        *delay = start.elapsed();
+        let result = self.message();
+        if result.as_ref().is_err_and(|error| error.kind() != super::Fault::Timeout) {
+            self.closed = true;
+        }
+        result
+    }
+
+    fn message(&mut self) -> Result<Response, Error> {
+        let mut kind = None;
+        let mut payload = Vec::new();
+        loop {
+            let (fin, opcode, data) = self.frame()?;
+            match opcode {
+                0x9 => {
+                    self.write(0xA, &data)?;
+                    continue;
+                }
+                0xA => continue,
+                0x8 => {
+                    let code = match data.get(..2) {
+                        Some(code) => u16::from_be_bytes([code[0], code[1]]),
+                        // a close frame without a code
+                        None => 1005,
+                    };
+                    if !self.closing {
+                        let _ = self.write(0x8, data.get(..2).unwrap_or_default());
+                    }
+                    self.closed = true;
+                    let reason = data.get(2..).unwrap_or_default().to_vec();
+                    return Response::build(String::new(), code, String::from("close"), Headers::default(), reason, true);
+                }
+                0x1 | 0x2 if kind.is_none() => kind = Some(opcode),
+                0x0 if kind.is_some() => {}
+                _ => return Err(Error::InvalidFrame),
+            }
+            payload.extend(data);
+            if payload.len() > LIMIT {
+                return Err(Error::InvalidFrame);
+            }
+            if fin {
+                break;
+            }
+        }
+        let reason = match kind {
+            Some(0x1) => "text",
+            _ => "binary",
+        };
+        Response::build(String::new(), 0, String::from(reason), Headers::default(), payload, true)
+    }
+
+    /// Reads a frame, returns whether it is the final one of its message, its opcode and its payload.
+    fn frame(&mut self) -> Result<(bool, u8, Vec<u8>), Error> {
+        let mut head = [0; 2];
+        self.reader.read_exact(&mut head).map_err(Error::ReadFailed)?;
+        let length = match head[1] & 0x7f {
+            126 => {
+                let mut length = [0; 2];
+                self.reader.read_exact(&mut length).map_err(Error::ReadFailed)?;
+                u16::from_be_bytes(length) as usize
+            }
+            127 => {
+                let mut length = [0; 8];
+                self.reader.read_exact(&mut length).map_err(Error::ReadFailed)?;
+                usize::try_from(u64::from_be_bytes(length)).unwrap_or(usize::MAX)
+            }
+            length => length as usize,
+        };
+        // servers do not mask their frames, one that does is unmasked all the same
+        let mut mask = [0; 4];
+        if head[1] & 0x80 != 0 {
+            self.reader.read_exact(&mut mask).map_err(Error::ReadFailed)?;
+        }
+        if length > LIMIT {
+            return Err(Error::InvalidFrame);
+        }
+        let mut payload = vec![0; length];
+        self.reader.read_exact(&mut payload).map_err(Error::ReadFailed)?;
+        payload.iter_mut().enumerate().for_each(|(i, byte)| *byte ^= mask[i % 4]);
+        Ok((head[0] & 0x80 != 0, head[0] & 0x0f, payload))
+    }
+
+    /// Writes a frame masked like every frame of a client.
+    fn write(&mut self, opcode: u8, payload: &[u8]) -> Result<(), Error> {
+        if self.closed || (self.closing && opcode != 0x8) {
+            return Err(Error::ConnectionClosed);
+        }
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            length @ 0..=125 => frame.push(0x80 | length as u8),
+            length @ 126..=0xffff => {
+                frame.push(0x80 | 126);
+                frame.extend((length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(0x80 | 127);
+                frame.extend((length as u64).to_be_bytes());
+            }
+        }
+        let mask = random::<4>();
+        frame.extend(mask);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        let writer = self.reader.get_mut();
+        writer.write_all(&frame).map_err(Error::WriteFailed)?;
+        writer.flush().map_err(|_e| Error::WriteFlushFailed)
+    }
+}
+
+/// Serves websockets that ping before echoing every message in two fragments, and echo the close.
+#[cfg(test)]
+pub(crate) fn echo() -> u16 {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream);
+                let mut key = String::new();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or_default() > 2 {
+                    if let Some((_, value)) = line.split_once("Sec-WebSocket-Key:") {
+                        key = value.trim().to_owned();
+                    }
+                    line.clear();
+                }
+                let head = format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                    accept(&key)
+                );
+                let _ = reader.get_mut().write_all(head.as_bytes());
+                loop {
+                    let mut head = [0; 2];
+                    if reader.read_exact(&mut head).is_err() {
+                        break;
+                    }
+                    let mut mask = [0; 4];
+                    let _ = reader.read_exact(&mut mask);
+                    let mut payload = vec![0; (head[1] & 0x7f) as usize];
+                    let _ = reader.read_exact(&mut payload);
+                    payload.iter_mut().enumerate().for_each(|(i, byte)| *byte ^= mask[i % 4]);
+                    let frames = match head[0] & 0x0f {
+                        0x8 => [vec![0x88, payload.len() as u8], payload].concat(),
+                        opcode @ (0x1 | 0x2) => {
+                            let (first, last) = payload.split_at(payload.len() / 2);
+                            [
+                                vec![0x89, 0x00, opcode, first.len() as u8],
+                                first.to_vec(),
+                                vec![0x80, last.len() as u8],
+                                last.to_vec(),
+                            ]
+                            .concat()
+                        }
+                        _ => continue,
+                    };
+                    let _ = reader.get_mut().write_all(&frames);
+                }
+            });
+        }
+    });
+    port
+}
+
+#[test]
+fn test_ws_accept() {
+    // the example of RFC 6455
+    assert_eq!(accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    assert_eq!(base64::engine::general_purpose::STANDARD.decode(key()).unwrap().len(), 16);
+}
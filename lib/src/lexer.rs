@@ -1,11 +1,21 @@
 use crate::Kind;
 use crate::Token;
+use std::cell::Cell;
 
 pub fn segment(text: &str) -> Vec<Token> {
     let mut tokens = Vec::new();
-    let mut chars = text.chars().peekable();
+    // line and column after the last char taken from the text
+    let position = Cell::new((1, 0));
+    let mut chars = text
+        .chars()
+        .inspect(|char| {
+            let (line, column) = position.get();
+            position.set(if *char == '\n' { (line + 1, 0) } else { (line, column + 1) })
+        })
+        .peekable();
     while let Some(char) = chars.next() {
         if !char.is_whitespace() {
+            let (line, column) = position.get();
             let (kind, literal) = match char {
                 '=' => {
                     if let Some(peek @ '=') = chars.peek() {
@@ -28,7 +38,18 @@ pub fn segment(text: &str) -> Vec<Token> {
                 '+' => (Kind::Add, String::from(char)),
                 '-' => (Kind::Sub, String::from(char)),
                 '*' => (Kind::Mul, String::from(char)),
-                '/' => (Kind::Div, String::from(char)),
+                '/' => {
+                    if let Some('/') = chars.peek() {
+                        chars.next();
+                        let mut string = String::new();
+                        while let Some(peek) = chars.next_if(|peek| *peek != '\n') {
+                            string.push(peek);
+                        }
+                        (Kind::Comment, string.trim_end().to_string())
+                    } else {
+                        (Kind::Div, String::from(char))
+                    }
+                }
                 '%' => (Kind::Rem, String::from(char)),
                 '^' => (Kind::Bx, String::from(char)),
                 '|' => {
@@ -98,6 +119,32 @@ pub fn segment(text: &str) -> Vec<Token> {
                     }
                     (Kind::String, string)
                 }
+                'r' if matches!(chars.peek(), Some('"') | Some('#')) => {
+                    let mut hashes = 0;
+                    while chars.next_if_eq(&'#').is_some() {
+                        hashes += 1;
+                    }
+                    if chars.next_if_eq(&'"').is_none() {
+                        (Kind::Illegal, format!("r{}", "#".repeat(hashes)))
+                    } else {
+                        // the literal is kept as it is written, so it prints back the same
+                        let mut string = format!("r{}\"", "#".repeat(hashes));
+                        while let Some(char) = chars.next() {
+                            string.push(char);
+                            if char == '"' {
+                                let mut count = 0;
+                                while count < hashes && chars.next_if_eq(&'#').is_some() {
+                                    count += 1;
+                                }
+                                string.push_str(&"#".repeat(count));
+                                if count == hashes {
+                                    break;
+                                }
+                            }
+                        }
+                        (Kind::Raw, string)
+                    }
+                }
                 '`' => {
                     let mut string = String::new();
                     while let Some(peek) = chars.peek() {
@@ -154,10 +201,11 @@ pub fn segment(text: &str) -> Vec<Token> {
                 }
                 _ => (Kind::Illegal, String::from(char)),
             };
-            tokens.push(Token::new(kind, literal));
+            tokens.push(Token::new(kind, literal, line, column));
         }
     }
-    tokens.push(Token::new(Kind::Eof, String::new()));
+    let (line, column) = position.get();
+    tokens.push(Token::new(Kind::Eof, String::new(), line, column + 1));
     tokens
 }
 
@@ -361,3 +409,58 @@ fn test_segment() {
         assert_eq!(literal, token.literal);
     }
 }
+
+#[test]
+fn test_segment_string() {
+    let text = r###"
+            "line one
+line two"
+            "C:\temp\new"
+            "\d{4}\n"
+            r"\d{4}\n"
+            r#"{"name": "raw"}"#
+            `{"name": "{name}"}`
+            "###;
+    let expect = vec![
+        (Kind::String, "line one\nline two"),
+        (Kind::String, r"C:\temp\new"),
+        (Kind::String, r"\d{4}\n"),
+        (Kind::Raw, r#"r"\d{4}\n""#),
+        (Kind::Raw, r##"r#"{"name": "raw"}"#"##),
+        (Kind::Template, r#"{"name": "{name}"}"#),
+        (Kind::Eof, ""),
+    ];
+    let tokens = segment(text);
+    assert_eq!(expect.len(), tokens.len());
+    for (i, (kind, literal)) in expect.into_iter().enumerate() {
+        let token = tokens.get(i).unwrap();
+        assert!(kind == token.kind);
+        assert_eq!(literal, token.literal);
+    }
+}
+
+#[test]
+fn test_segment_comment() {
+    let text = "let a = 1; // one\n// two\na / 2";
+    let expect = vec![
+        (Kind::Let, "let", 1, 1),
+        (Kind::Ident, "a", 1, 5),
+        (Kind::Assign, "=", 1, 7),
+        (Kind::Integer, "1", 1, 9),
+        (Kind::Semi, ";", 1, 10),
+        (Kind::Comment, " one", 1, 12),
+        (Kind::Comment, " two", 2, 1),
+        (Kind::Ident, "a", 3, 1),
+        (Kind::Div, "/", 3, 3),
+        (Kind::Integer, "2", 3, 5),
+        (Kind::Eof, "", 3, 6),
+    ];
+    let tokens = segment(text);
+    assert_eq!(expect.len(), tokens.len());
+    for (i, (kind, literal, line, column)) in expect.into_iter().enumerate() {
+        let token = tokens.get(i).unwrap();
+        assert!(kind == token.kind);
+        assert_eq!(literal, token.literal);
+        assert_eq!((line, column), (token.line, token.column));
+    }
+}
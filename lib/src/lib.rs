@@ -1,7 +1,11 @@
+mod checker;
+mod compiler;
 mod context;
 mod evaluator;
+mod formatter;
 mod http;
 mod lexer;
+mod machine;
 mod native;
 mod parser;
 mod stat;
@@ -13,13 +17,23 @@ mod writer;
 use context::Assert;
 use context::Record;
 use syntax::Expr;
-use token::Kind;
-use token::Token;
-use value::Value;
 
+pub use checker::check;
+pub use checker::render;
+pub use checker::Problem;
+pub use compiler::compile;
+pub use compiler::Program;
 pub use context::Context;
 pub use evaluator::eval_block;
+pub use formatter::format;
+pub use http::FIELDS;
+pub use lexer::segment;
+pub use machine::Machine;
+pub use native::NAMES as NATIVES;
 pub use parser::Parser;
 pub use parser::Source;
 pub use stat::Stats;
+pub use token::Kind;
+pub use token::Token;
+pub use value::Value;
 pub use writer::Writer;
@@ -0,0 +1,497 @@
+use crate::compiler::Op;
+use crate::compiler::Program;
+use crate::compiler::Segment;
+use crate::compiler::Step;
+use crate::evaluator::call;
+use crate::http;
+use crate::Assert;
+use crate::Context;
+use crate::Kind;
+use crate::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+static NULL: Value = Value::Null;
+
+/// Runs a compiled program, variables live in slots that persist between runs.
+pub struct Machine {
+    program: Arc<Program>,
+    slots: Vec<Option<Value>>,
+}
+
+impl Machine {
+    /// Creates a machine whose slots start with the variables of the context.
+    pub fn new(program: Arc<Program>, context: &Context) -> Self {
+        let slots = program.names.iter().map(|name| context.get(name).cloned()).collect();
+        Self { program, slots }
+    }
+
+    /// Runs the program, records of the requests it sends are pushed to the context.
+    pub fn run(&mut self, context: &mut Context) -> Result<Value, String> {
+        let program = self.program.as_ref();
+        execute(&program.code, &mut self.slots, &program.names, program, context)
+    }
+}
+
+fn execute(
+    code: &[Op],
+    slots: &mut [Option<Value>],
+    names: &[String],
+    program: &Program,
+    context: &mut Context,
+) -> Result<Value, String> {
+    let mut stack: Vec<Value> = Vec::new();
+    let mut pointer = 0;
+    while let Some(op) = code.get(pointer) {
+        pointer += 1;
+        match op {
+            Op::Push(value) => stack.push(value.to_owned()),
+            Op::Pop => {
+                stack.pop();
+            }
+            Op::Load(slot) => match &slots[*slot] {
+                Some(value) => stack.push(value.to_owned()),
+                None => return Err(format!("ident:{} not found", names[*slot])),
+            },
+            Op::LoadPath(slot, steps) => {
+                let mut value = match &slots[*slot] {
+                    Some(value) => value,
+                    None => return Err(format!("ident:{} not found", names[*slot])),
+                };
+                for step in steps {
+                    value = match step {
+                        Step::Field(name) => field(value, name)?,
+                        Step::Index(index) => self::index(value, index)?,
+                    };
+                }
+                stack.push(value.to_owned());
+            }
+            Op::Store(slot) => slots[*slot] = stack.last().cloned(),
+            Op::Template(segments) => stack.push(Value::String(interpolate(segments, slots, false))),
+            Op::Array(length) => {
+                let items = stack.split_off(stack.len() - length);
+                stack.push(Value::Array(items));
+            }
+            Op::Map(length) => {
+                let mut items = stack.split_off(stack.len() - length * 2).into_iter();
+                let mut map = HashMap::new();
+                while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                    map.insert(key.to_string(), value);
+                }
+                stack.push(Value::Map(map));
+            }
+            Op::Index => {
+                let index = pop(&mut stack);
+                let value = pop(&mut stack);
+                stack.push(self::index(&value, &index)?.to_owned());
+            }
+            Op::Field(name) => {
+                let value = pop(&mut stack);
+                stack.push(field(&value, name)?.to_owned());
+            }
+            Op::Unary(token) => {
+                let value = match (token.kind, pop(&mut stack)) {
+                    (Kind::Not, Value::Boolean(false)) | (Kind::Not, Value::Null) => Value::Boolean(true),
+                    (Kind::Not, Value::Integer(integer)) => Value::Integer(!integer),
+                    (Kind::Not, _) => Value::Boolean(false),
+                    (Kind::Sub, Value::Integer(integer)) => Value::Integer(-integer),
+                    (Kind::Sub, Value::Float(float)) => Value::Float(-float),
+                    (_, right) => return Err(format!("unknown operator: {}{:?}", token, right)),
+                };
+                stack.push(value);
+            }
+            Op::Binary(token) => {
+                let right = pop(&mut stack);
+                let left = pop(&mut stack);
+                let value = match token.kind {
+                    Kind::Add => (left + right)?,
+                    Kind::Sub => (left - right)?,
+                    Kind::Mul => (left * right)?,
+                    Kind::Div => (left / right)?,
+                    Kind::Rem => (left % right)?,
+                    Kind::Bx => (left ^ right)?,
+                    Kind::Bo => (left | right)?,
+                    Kind::Ba => (left & right)?,
+                    Kind::Sl => (left << right)?,
+                    Kind::Sr => (left >> right)?,
+                    kind => Value::Boolean(compare(kind, &left, &right).unwrap_or_default()),
+                };
+                stack.push(value);
+            }
+            Op::JumpIfTrue(target) => match stack.last() {
+                Some(Value::Boolean(false) | Value::Null) => {
+                    stack.pop();
+                }
+                _ => pointer = *target,
+            },
+            Op::JumpIfFalse(target) => match stack.last() {
+                Some(Value::Boolean(false) | Value::Null) => pointer = *target,
+                _ => {
+                    stack.pop();
+                }
+            },
+            Op::JumpUnless(target) => {
+                if let Value::Boolean(false) | Value::Null = pop(&mut stack) {
+                    pointer = *target;
+                }
+            }
+            Op::Jump(target) => pointer = *target,
+            Op::Native(function, length) => {
+                let arguments = stack.split_off(stack.len() - length);
+                stack.push(function(arguments)?);
+            }
+            Op::Stateful(function, length) => {
+                let arguments = stack.split_off(stack.len() - length);
+                stack.push(function(arguments, context)?);
+            }
+            Op::Request(index, length) => {
+                let request = &program.requests[*index];
+                let values = stack.split_off(stack.len() - request.options.len());
+                let options = request.options.iter().cloned().zip(values).collect();
+                stack.truncate(stack.len() - length);
+                let message = interpolate(&request.message, slots, true);
+                let value = call(&request.name, &message, options, context, |map| {
+                    // response fields move into the slots of the asserts and back into the map afterwards,
+                    // the asserts see nothing else, like the evaluator runs them
+                    let mut locals = request.names.iter().map(|name| map.remove(name)).collect::<Vec<Option<Value>>>();
+                    let context = &mut Context::default();
+                    let asserts = request
+                        .asserts
+                        .iter()
+                        .map(|assert| {
+                            let left = execute(&assert.left, &mut locals, &request.names, program, context).unwrap_or(Value::Null);
+                            let right = execute(&assert.right, &mut locals, &request.names, program, context).unwrap_or(Value::Null);
+                            Assert {
+                                expr: assert.expr.to_owned(),
+                                result: compare(assert.token.kind, &left, &right).unwrap_or_default(),
+                                left: left.to_string(),
+                                compare: assert.token.to_string(),
+                                right: right.to_string(),
+                            }
+                        })
+                        .collect::<Vec<Assert>>();
+                    for (name, value) in request.names.iter().zip(locals) {
+                        if let Some(value) = value {
+                            map.insert(name.to_owned(), value);
+                        }
+                    }
+                    asserts
+                })?;
+                stack.push(value);
+            }
+            Op::Call(name) => return Err(format!("function {} not found", name)),
+            Op::Fail(message) => return Err(message.to_owned()),
+        }
+    }
+    Ok(stack.pop().unwrap_or(Value::Null))
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().unwrap_or(Value::Null)
+}
+
+fn compare(kind: Kind, left: &Value, right: &Value) -> Option<bool> {
+    match kind {
+        Kind::Lt => Some(left < right),
+        Kind::Gt => Some(left > right),
+        Kind::Le => Some(left <= right),
+        Kind::Ge => Some(left >= right),
+        Kind::Eq => Some(left == right),
+        Kind::Ne => Some(left != right),
+        _ => None,
+    }
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, String> {
+    match value {
+        Value::Map(pairs) => Ok(pairs.get(name).unwrap_or(&NULL)),
+        value => Err(format!("field operator not support: {:?}", value)),
+    }
+}
+
+fn index<'a>(value: &'a Value, index: &Value) -> Result<&'a Value, String> {
+    match (value, index) {
+        (Value::Array(items), Value::Integer(index)) => Ok(items.get(*index as usize).unwrap_or(&NULL)),
+        (Value::Map(pairs), key) => Ok(pairs.get(&key.to_string()).unwrap_or(&NULL)),
+        (value, _) => Err(format!("index operator not support: {:?}", value)),
+    }
+}
+
+fn interpolate(segments: &[Segment], slots: &[Option<Value>], message: bool) -> String {
+    let mut string = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Static(text) => string.push_str(text),
+            Segment::Slot(slot) => {
+                let value = match &slots[*slot] {
+                    Some(Value::String(text)) => Cow::Borrowed(text.as_str()),
+                    Some(value) => Cow::Owned(value.to_string()),
+                    None => Cow::Owned(Value::Null.to_string()),
+                };
+                match message {
+                    true => string.push_str(&http::escape(&string, &value)),
+                    false => string.push_str(&value),
+                }
+            }
+        }
+    }
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Machine;
+    use crate::compile;
+    use crate::eval_block;
+    use crate::parser::Parser;
+    use crate::parser::Source;
+    use crate::Context;
+    use crate::Value;
+    use std::sync::Arc;
+
+    fn run_machine_tests(tests: Vec<(&str, Value)>) {
+        for (text, expect) in tests {
+            let Source { exprs, requests, .. } = Parser::new(text).parse().unwrap();
+            let mut context = Context::new();
+            context.extend(requests);
+            let program = Arc::new(compile(&exprs, &context));
+            let mut machine = Machine::new(program, &context);
+            let value = machine.run(&mut context);
+            let mut context = Context::new();
+            assert_eq!(value, eval_block(&exprs, &mut context), "{}", text);
+            assert_eq!(value, Ok(expect), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_machine_values() {
+        let tests = vec![
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", Value::Integer(50)),
+            ("!!5 + (5 ^ 3) + (5 << 2) + (-5 >> 2)", Value::Integer(29)),
+            ("(0.5 + 1.5 * 0.2 + 1.5 / 3.0) * 2.0 + -1.0", Value::Float(1.6)),
+            ("!(if (false) { 5; })", Value::Boolean(true)),
+            ("\"Cat\" || false", Value::String(String::from("Cat"))),
+            ("\"Cat\" && \"Dog\"", Value::String(String::from("Dog"))),
+            ("false && \"Cat\"", Value::Boolean(false)),
+            ("2 == 3 || (4 < 0 && 1 == 1)", Value::Boolean(false)),
+            (
+                "let name = \"Bob\"; `Hello {name}, {missing}!`",
+                Value::String(String::from("Hello Bob, null!")),
+            ),
+            ("[1, 2, 3][0 + 2]", Value::Integer(3)),
+            ("[[1, 1, 1]][0][0]", Value::Integer(1)),
+            ("[1][-1]", Value::Null),
+            ("{1 + 1: 2 * 2, 3 + 3: 4 * 4}[6]", Value::Integer(16)),
+            (
+                "let map = {\"a\": {\"b\": [1, 2]}}; map.a.b[1] + map[\"a\"].b[0]",
+                Value::Integer(3),
+            ),
+            ("let map = {\"a\": 1}; map.b", Value::Null),
+            ("let one = 1;let two = 2;let one = 3;one + two", Value::Integer(5)),
+            ("if (1 > 2) { 10 }", Value::Null),
+            ("if (true) {} else { 10 }", Value::Null),
+            ("if (true) { 1; 2 } else { 3 }", Value::Integer(2)),
+            ("let x = if (false) { 1 } else { let y = 2; y * 3 }; x + y", Value::Integer(8)),
+            ("length(append([1], 2))", Value::Integer(2)),
+            ("format(\"{x}-{y}\", 1, \"a\")", Value::String(String::from("1-a"))),
+        ];
+        run_machine_tests(tests);
+    }
+
+    #[test]
+    fn test_machine_errors() {
+        let tests = vec![
+            "missing + 1",
+            "let map = {}; map.a.b",
+            "let array = [1]; array.a",
+            "1[0]",
+            "-true",
+            "1 + \"a\"",
+            "undefined(println(1))",
+            "request get(redirects: -1)`GET http://127.0.0.1:1/`; get()",
+            "request get(timeout: \"1s\")`GET http://127.0.0.1:1/`; get()",
+            "request get(pins: [1])`GET http://127.0.0.1:1/`; get()",
+            "request get(insecure: \"yes\")`GET http://127.0.0.1:1/`; get()",
+            "request get(resolve: \"example.com\")`GET http://127.0.0.1:1/`; get()",
+            "request get(family: \"ipv5\")`GET http://127.0.0.1:1/`; get()",
+            "request get(proxy: \"ftp://127.0.0.1:1\")`GET http://127.0.0.1:1/`; get()",
+            "request get(no_proxy: 1)`GET http://127.0.0.1:1/`; get()",
+            "request get(events: -1)`GET http://127.0.0.1:1/`; get()",
+            "request get(events_timeout: true)`GET http://127.0.0.1:1/`; get()",
+            "request get(upload_rate: -1)`GET http://127.0.0.1:1/`; get()",
+            "request get(latency: \"1s\")`GET http://127.0.0.1:1/`; get()",
+            "request get(attempts: 0)`GET http://127.0.0.1:1/`; get()",
+            "request get(retry_on: [503, \"sometimes\"])`GET http://127.0.0.1:1/`; get()",
+        ];
+        for text in tests {
+            let Source { exprs, requests, .. } = Parser::new(text).parse().unwrap();
+            let mut context = Context::new();
+            context.extend(requests.to_owned());
+            let mut machine = Machine::new(Arc::new(compile(&exprs, &context)), &context);
+            let value = machine.run(&mut context);
+            assert!(value.is_err(), "{}", text);
+            let mut context = Context::new();
+            context.extend(requests);
+            assert_eq!(value, eval_block(&exprs, &mut context), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_machine_request() {
+        use std::io::Read;
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().take(2).flatten() {
+                let mut buffer = [0; 1024];
+                let _ = stream.read(&mut buffer);
+                let body = r#"{"id": 7}"#;
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+            }
+        });
+        let text = format!(
+            r#"
+            let port = {};
+            let limit = 0;
+            request get(redirects: limit)`
+                GET http://127.0.0.1:{{port}}/get
+                Connection: close
+            `[status == 200, json.id == 7, status > code];
+            let response = get();
+            [response.status, response.json.id]
+            "#,
+            port
+        );
+        let Source { exprs, requests, .. } = Parser::new(&text).parse().unwrap();
+        let mut context = Context::new();
+        context.extend(requests);
+        let mut machine = Machine::new(Arc::new(compile(&exprs, &context)), &context);
+        let mut compiled = context.clone();
+        let value = machine.run(&mut compiled);
+        assert_eq!(value, Ok(Value::Array(vec![Value::Integer(200), Value::Integer(7)])));
+        assert_eq!(value, eval_block(&exprs, &mut context));
+        let (compiled, evaluated) = (compiled.records(), context.records());
+        assert_eq!(compiled.len(), 1);
+        let results = |records: &[crate::Record]| records[0].asserts.iter().map(|assert| assert.to_string()).collect::<Vec<String>>();
+        assert_eq!(results(&compiled), results(&evaluated));
+        assert_eq!(results(&compiled)[2], "status > code => (200 > null) => false");
+    }
+
+    #[test]
+    fn test_machine_websocket() {
+        let text = format!(
+            r#"
+            request chat`GET ws://127.0.0.1:{}/chat`[status == 101];
+            chat();
+            let sent = ws_send("hello");
+            let message = ws_recv(1000);
+            let closed = ws_close(4000, 1000);
+            [sent, message.reason, message.body, closed.status]
+            "#,
+            crate::http::echo()
+        );
+        let Source { exprs, requests, .. } = Parser::new(&text).parse().unwrap();
+        let mut context = Context::new();
+        context.extend(requests);
+        let mut machine = Machine::new(Arc::new(compile(&exprs, &context)), &context);
+        let mut compiled = context.clone();
+        let value = machine.run(&mut compiled);
+        let expected = vec![
+            Value::Boolean(true),
+            Value::String(String::from("text")),
+            Value::String(String::from("hello")),
+            Value::Integer(4000),
+        ];
+        assert_eq!(value, Ok(Value::Array(expected)));
+        assert_eq!(value, eval_block(&exprs, &mut context));
+        let names = |records: Vec<crate::Record>| records.into_iter().map(|record| record.name).collect::<Vec<String>>();
+        let expected = ["chat", "chat.send", "chat.recv", "chat.close"].map(String::from).to_vec();
+        assert_eq!((names(compiled.records()), names(context.records())), (expected.clone(), expected));
+        assert_eq!(
+            eval_block(&Parser::new("ws_recv(10)").parse().unwrap().exprs, &mut context),
+            Err(String::from("no websocket open"))
+        );
+    }
+
+    #[test]
+    fn test_machine_assert_context() {
+        let text = format!(
+            r#"
+            request chat`GET ws://127.0.0.1:{}/chat`[status == 101, ws_send("hello") == true];
+            chat();
+            ws_close(1000, 1000).status
+            "#,
+            crate::http::echo()
+        );
+        let Source { exprs, requests, .. } = Parser::new(&text).parse().unwrap();
+        let mut context = Context::new();
+        context.extend(requests);
+        let mut machine = Machine::new(Arc::new(compile(&exprs, &context)), &context);
+        let mut compiled = context.clone();
+        let value = machine.run(&mut compiled);
+        assert_eq!(value, Ok(Value::Integer(1000)));
+        assert_eq!(value, eval_block(&exprs, &mut context));
+        // the asserts do not see the websocket the request opened
+        let asserts = |records: Vec<crate::Record>| {
+            records
+                .into_iter()
+                .flat_map(|record| record.asserts.into_iter().map(|assert| assert.to_string()))
+                .collect::<Vec<String>>()
+        };
+        let (compiled, evaluated) = (asserts(compiled.records()), asserts(context.records()));
+        assert_eq!(compiled, evaluated);
+        assert_eq!(compiled[1], "ws_send(\"hello\") == true => (null == true) => false");
+    }
+
+    #[test]
+    fn test_machine_retry() {
+        use std::io::BufRead;
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            // two of every three requests are answered unavailable
+            for (connection, stream) in listener.incoming().flatten().enumerate() {
+                let mut reader = std::io::BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or_default() > 2 {
+                    line.clear();
+                }
+                let status = if connection % 3 == 2 { "200 OK" } else { "503 Service Unavailable" };
+                let _ = write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+            }
+        });
+        let text = format!(
+            r#"
+            request get(attempts: 5, retry_on: [502, 503], backoff: 10)`GET http://127.0.0.1:{}/`[status == 200];
+            get().status
+            "#,
+            port
+        );
+        let Source { exprs, requests, .. } = Parser::new(&text).parse().unwrap();
+        let mut context = Context::new();
+        context.extend(requests);
+        let mut machine = Machine::new(Arc::new(compile(&exprs, &context)), &context);
+        let mut compiled = context.clone();
+        let value = machine.run(&mut compiled);
+        assert_eq!(value, Ok(Value::Integer(200)));
+        assert_eq!(value, eval_block(&exprs, &mut context));
+        let attempts = |records: Vec<crate::Record>| {
+            records
+                .into_iter()
+                .map(|record| (record.attempt, record.response.status, record.asserts[0].result))
+                .collect::<Vec<(u32, u16, bool)>>()
+        };
+        let expected = vec![(1, 503, false), (2, 503, false), (3, 200, true)];
+        assert_eq!(
+            (attempts(compiled.records()), attempts(context.records())),
+            (expected.clone(), expected)
+        );
+    }
+}
@@ -1,4 +1,50 @@
+use crate::http;
+use crate::http::Fault;
+use crate::http::Request;
+use crate::http::Response;
+use crate::http::Socket;
+use crate::http::Time;
+use crate::Context;
+use crate::Record;
 use crate::Value;
+use base64::Engine;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Names of the native functions callable from scripts.
+pub const NAMES: [&str; 11] = [
+    "println", "print", "format", "length", "append", "base64", "sha1", "sha256", "ws_send", "ws_recv", "ws_close",
+];
+
+pub type Native = fn(Vec<Value>) -> Result<Value, String>;
+
+/// A native function that uses the state of the virtual user, like the websocket it opened.
+pub type Stateful = fn(Vec<Value>, &mut Context) -> Result<Value, String>;
+
+/// Finds the native function with the name.
+pub fn function(name: &str) -> Option<Native> {
+    match name {
+        "println" => Some(println),
+        "print" => Some(print),
+        "format" => Some(format),
+        "length" => Some(length),
+        "append" => Some(append),
+        "base64" => Some(base64),
+        "sha1" => Some(sha1),
+        "sha256" => Some(sha256),
+        _ => None,
+    }
+}
+
+/// Finds the native function with the name among those that use the state of the virtual user.
+pub fn stateful(name: &str) -> Option<Stateful> {
+    match name {
+        "ws_send" => Some(ws_send),
+        "ws_recv" => Some(ws_recv),
+        "ws_close" => Some(ws_close),
+        _ => None,
+    }
+}
 
 pub fn println(values: Vec<Value>) -> Result<Value, String> {
     match format(values) {
@@ -50,6 +96,7 @@ pub fn length(values: Vec<Value>) -> Result<Value, String> {
     } else if let Some(value) = values.first() {
         match value {
             Value::String(string) => Ok(Value::Integer(string.len() as i64)),
+            Value::Bytes(bytes) => Ok(Value::Integer(bytes.len() as i64)),
             Value::Array(items) => Ok(Value::Integer(items.len() as i64)),
             Value::Map(pairs) => Ok(Value::Integer(pairs.len() as i64)),
             _ => Err(format!("function length not supported type {:?}", value)),
@@ -73,6 +120,120 @@ pub fn append(mut values: Vec<Value>) -> Result<Value, String> {
     }
 }
 
+/// The bytes of a string or bytes value, which encoders and hashes take.
+fn bytes<'a>(name: &str, values: &'a [Value]) -> Result<&'a [u8], String> {
+    match values {
+        [Value::String(string)] => Ok(string.as_bytes()),
+        [Value::Bytes(bytes)] => Ok(bytes),
+        [value] => Err(format!("function {} not supported type {:?}", name, value)),
+        values => Err(format!("wrong number of arguments. got={}, want=1", values.len())),
+    }
+}
+
+pub fn base64(values: Vec<Value>) -> Result<Value, String> {
+    let bytes = bytes("base64", &values)?;
+    Ok(Value::String(base64::engine::general_purpose::STANDARD.encode(bytes)))
+}
+
+pub fn sha1(values: Vec<Value>) -> Result<Value, String> {
+    let bytes = bytes("sha1", &values)?;
+    Ok(Value::String(hex(ring::digest::digest(
+        &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+        bytes,
+    )
+    .as_ref())))
+}
+
+pub fn sha256(values: Vec<Value>) -> Result<Value, String> {
+    let bytes = bytes("sha256", &values)?;
+    Ok(Value::String(hex(ring::digest::digest(&ring::digest::SHA256, bytes).as_ref())))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| std::format!("{:02x}", byte)).collect()
+}
+
+/// Sends a string as a text message or bytes as a binary one on the open websocket, returns whether it was sent.
+pub fn ws_send(values: Vec<Value>, context: &mut Context) -> Result<Value, String> {
+    let (payload, text) = match &values[..] {
+        [Value::String(string)] => (string.as_bytes(), true),
+        [Value::Bytes(bytes)] => (&bytes[..], false),
+        [value] => return Err(format!("function ws_send not supported type {:?}", value)),
+        values => return Err(format!("wrong number of arguments. got={}, want=1", values.len())),
+    };
+    let (_, sent) = exchange(context, "send", |socket| socket.send(payload, text))?;
+    Ok(Value::Boolean(sent))
+}
+
+/// Waits for a message on the open websocket, at most the milliseconds if given.
+/// Returns it like a response, with `text`, `binary` or `close` as the reason and the close code as the status,
+/// or null if none came.
+pub fn ws_recv(values: Vec<Value>, context: &mut Context) -> Result<Value, String> {
+    let timeout = millis("ws_recv", values.first())?;
+    if values.len() > 1 {
+        return Err(format!("wrong number of arguments. got={}, want=1", values.len()));
+    }
+    match exchange(context, "recv", |socket| socket.receive(timeout))? {
+        (map, true) => Ok(Value::Map(map)),
+        (_, false) => Ok(Value::Null),
+    }
+}
+
+/// Closes the open websocket with the code, 1000 if none is given, and waits at most the milliseconds if given
+/// for the close of the server. Returns it like `ws_recv` does.
+pub fn ws_close(values: Vec<Value>, context: &mut Context) -> Result<Value, String> {
+    let code = match values.first() {
+        None => 1000,
+        Some(Value::Integer(code)) if (1000..=4999).contains(code) => *code as u16,
+        Some(value) => return Err(format!("function ws_close not supported code {:?}", value)),
+    };
+    let timeout = millis("ws_close", values.get(1))?;
+    if values.len() > 2 {
+        return Err(format!("wrong number of arguments. got={}, want=2", values.len()));
+    }
+    match exchange(context, "close", |socket| socket.close(code, timeout))? {
+        (map, true) => Ok(Value::Map(map)),
+        (_, false) => Ok(Value::Null),
+    }
+}
+
+/// A wait in milliseconds, zero or none waits without a limit.
+fn millis(name: &str, value: Option<&Value>) -> Result<Option<Duration>, String> {
+    match value {
+        None | Some(Value::Integer(0)) => Ok(None),
+        Some(Value::Integer(millis)) if *millis > 0 => Ok(Some(Duration::from_millis(*millis as u64))),
+        Some(value) => Err(format!("function {} not supported timeout {:?}", name, value)),
+    }
+}
+
+/// Exchanges a message on the open websocket and records it under the name of the request that opened it,
+/// returns the response map and whether it went without an error.
+fn exchange(
+    context: &mut Context,
+    action: &str,
+    f: impl FnOnce(&mut Socket) -> (Request, Response, Time, Option<http::Error>),
+) -> Result<(HashMap<String, Value>, bool), String> {
+    let (name, socket) = context.socket().ok_or_else(|| String::from("no websocket open"))?;
+    let name = std::format!("{}.{}", name, action);
+    let (request, response, time, error) = f(socket);
+    let kind = error.as_ref().map_or(Fault::None, http::Error::kind);
+    let ok = error.is_none();
+    let error = error.map(|error| error.to_string()).unwrap_or_default();
+    let map = response.to_map();
+    context.forget();
+    context.push(Record {
+        name,
+        request,
+        response,
+        time,
+        error,
+        kind,
+        asserts: Vec::new(),
+        attempt: 1,
+    });
+    Ok((map, ok))
+}
+
 #[test]
 fn test_format() {
     let tests = vec![
@@ -137,3 +298,24 @@ fn test_append() {
         }
     }
 }
+
+#[test]
+fn test_encode_hash() {
+    let tests = vec![
+        (base64(vec![Value::String(String::from("hello"))]), "aGVsbG8="),
+        (base64(vec![Value::Bytes(vec![0xff, 0x00])]), "/wA="),
+        (
+            sha1(vec![Value::String(String::from("hello"))]),
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d",
+        ),
+        (
+            sha256(vec![Value::Bytes(b"hello".to_vec())]),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        ),
+    ];
+    for (actual, expected) in tests {
+        assert_eq!(actual, Ok(Value::String(String::from(expected))));
+    }
+    assert_eq!(length(vec![Value::Bytes(vec![0xff, 0x00])]), Ok(Value::Integer(2)));
+    assert!(sha256(vec![Value::Integer(1)]).is_err());
+}
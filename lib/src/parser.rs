@@ -3,32 +3,80 @@ use crate::Expr;
 use crate::Kind;
 use crate::Token;
 use std::collections::HashMap;
+use std::collections::VecDeque;
+
+/// A request declaration: its message template, asserts and options.
+pub type Declaration = (String, Vec<Expr>, Vec<(String, Expr)>);
 
 pub struct Source {
     pub exprs: Vec<Expr>,
-    pub requests: HashMap<String, (String, Vec<Expr>)>,
+    pub requests: HashMap<String, Declaration>,
     pub tests: HashMap<String, Vec<Expr>>,
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
     index: usize,
+    comments: VecDeque<Token>,
+    trivia: bool,
+    /// Requests and tests defined again after their first definition, by keyword and name
+    duplicates: Vec<(Token, Token)>,
 }
 
 impl Parser {
     pub fn new(text: &str) -> Parser {
+        let (comments, tokens): (Vec<Token>, Vec<Token>) =
+            lexer::segment(text).into_iter().partition(|token| token.kind == Kind::Comment);
         Parser {
-            tokens: lexer::segment(text),
+            tokens,
             index: usize::MIN,
+            comments: VecDeque::from(comments),
+            trivia: false,
+            duplicates: Vec::new(),
+        }
+    }
+
+    /// Creates a parser that keeps comments as statements inside blocks, and among the items of lists and maps.
+    pub(crate) fn with_comments(text: &str) -> Parser {
+        Parser {
+            trivia: true,
+            ..Parser::new(text)
+        }
+    }
+
+    /// Takes the comments that appear before the token.
+    pub(crate) fn take_comments(&mut self, token: &Token) -> Vec<Token> {
+        let mut comments = Vec::new();
+        while let Some(comment) = self.comments.pop_front() {
+            if (comment.line, comment.column) < (token.line, token.column) {
+                comments.push(comment);
+            } else {
+                self.comments.push_front(comment);
+                break;
+            }
+        }
+        comments
+    }
+
+    /// Takes the comment that follows the current token on the line it ends on.
+    pub(crate) fn take_trailing(&mut self) -> Option<Token> {
+        let current = self.current_token();
+        let comment = self.comments.front()?;
+        let position = (comment.line, comment.column);
+        let after = position > (current.line, current.column);
+        let before = self.peek_token().is_none_or(|peek| position < (peek.line, peek.column));
+        match comment.line == current.end() && after && before {
+            true => self.comments.pop_front(),
+            false => None,
         }
     }
 
-    fn next_token(&mut self) {
+    pub(crate) fn next_token(&mut self) {
         let index = self.index + 1;
         (index < self.tokens.len()).then(|| self.index = index);
     }
 
-    fn current_token(&self) -> &Token {
+    pub(crate) fn current_token(&self) -> &Token {
         &self.tokens[self.index]
     }
 
@@ -36,7 +84,7 @@ impl Parser {
         self.tokens.get(self.index + 1)
     }
 
-    fn peek_token_is(&self, kind: Kind) -> bool {
+    pub(crate) fn peek_token_is(&self, kind: Kind) -> bool {
         matches!(self.peek_token(), Some(peek) if kind == peek.kind)
     }
 
@@ -65,6 +113,16 @@ impl Parser {
         }
     }
 
+    /// The token the parser stopped at, which locates a parse error.
+    pub fn token(&self) -> &Token {
+        self.current_token()
+    }
+
+    /// The names of the requests and tests parsed that were defined before, the later definition wins.
+    pub fn duplicates(&self) -> &[(Token, Token)] {
+        &self.duplicates
+    }
+
     pub fn parse(&mut self) -> Result<Source, String> {
         let mut exprs = Vec::new();
         let mut requests = HashMap::new();
@@ -72,12 +130,18 @@ impl Parser {
         while self.current_token().kind != Kind::Eof {
             match self.current_token().kind {
                 Kind::Request => {
-                    let (name, message, asserts) = self.parse_request_literal()?;
-                    requests.insert(name, (message, asserts));
+                    let tokens = (self.current_token().clone(), self.peek_token().cloned());
+                    let (name, declaration) = self.parse_request_literal()?;
+                    if let (true, (keyword, Some(name))) = (requests.insert(name, declaration).is_some(), tokens) {
+                        self.duplicates.push((keyword, name));
+                    }
                 }
                 Kind::Test => {
+                    let tokens = (self.current_token().clone(), self.peek_token().cloned());
                     let (name, block) = self.parse_test_literal()?;
-                    tests.insert(name, block);
+                    if let (true, (keyword, Some(name))) = (tests.insert(name, block).is_some(), tokens) {
+                        self.duplicates.push((keyword, name));
+                    }
                 }
                 _ => exprs.push(self.parse_expr(u8::MIN)?),
             }
@@ -89,13 +153,15 @@ impl Parser {
         Ok(Source { exprs, requests, tests })
     }
 
-    fn parse_expr(&mut self, mut precedence: u8) -> Result<Expr, String> {
+    pub(crate) fn parse_expr(&mut self, mut precedence: u8) -> Result<Expr, String> {
         let mut left = match self.current_token().kind {
             Kind::Ident => self.parse_ident_expr(),
             Kind::Integer => self.parse_integer_literal()?,
             Kind::Float => self.parse_float_literal()?,
             Kind::True | Kind::False => self.parse_boolean_literal()?,
             Kind::String => self.parse_string_literal(),
+            Kind::Raw => self.parse_raw_literal(),
+            Kind::Template => self.parse_template_literal(),
             Kind::Let => {
                 precedence = u8::MAX;
                 self.parse_let_expr()?
@@ -184,6 +250,18 @@ impl Parser {
         Expr::String(self.parse_current_string())
     }
 
+    fn parse_raw_literal(&self) -> Expr {
+        let literal = self.parse_current_string();
+        let hashes = literal[1..].chars().take_while(|char| *char == '#').count();
+        let string = &literal[hashes + 2..];
+        let string = string.strip_suffix(&format!("\"{}", "#".repeat(hashes))).unwrap_or(string);
+        Expr::Raw(string.to_owned(), hashes)
+    }
+
+    fn parse_template_literal(&self) -> Expr {
+        Expr::Template(self.parse_current_string())
+    }
+
     fn parse_let_expr(&mut self) -> Result<Expr, String> {
         self.peek_token_expect(Kind::Ident)?;
         let name = self.parse_current_string();
@@ -248,12 +326,14 @@ impl Parser {
     fn parse_expr_list(&mut self, end: Kind) -> Result<Vec<Expr>, String> {
         let mut exprs = Vec::new();
         while !self.peek_token_is(end) {
+            exprs.extend(self.trivia());
             self.next_token();
             exprs.push(self.parse_expr(u8::MIN)?);
             if !self.peek_token_is(end) {
                 self.peek_token_expect(Kind::Comma)?;
             }
         }
+        exprs.extend(self.trivia());
         self.peek_token_expect(end)?;
         Ok(exprs)
     }
@@ -264,8 +344,11 @@ impl Parser {
     }
 
     fn parse_map_literal(&mut self) -> Result<Expr, String> {
+        // a comment among the pairs stands as both the key and the value of one
+        let comments = |comments: Vec<Expr>| comments.into_iter().map(|comment| (comment.clone(), comment));
         let mut pairs = Vec::new();
         while !self.peek_token_is(Kind::Rb) {
+            pairs.extend(comments(self.trivia()));
             self.next_token();
             let key = self.parse_expr(u8::MIN)?;
             self.peek_token_expect(Kind::Colon)?;
@@ -276,6 +359,7 @@ impl Parser {
                 self.peek_token_expect(Kind::Comma)?;
             }
         }
+        pairs.extend(comments(self.trivia()));
         self.peek_token_expect(Kind::Rb)?;
         Ok(Expr::Map(pairs))
     }
@@ -297,19 +381,41 @@ impl Parser {
         let mut exprs = Vec::new();
         self.peek_token_expect(Kind::Lb)?;
         while !self.peek_token_is(Kind::Rb) {
+            exprs.extend(self.trivia());
             self.next_token();
             exprs.push(self.parse_expr(u8::MIN)?);
             if self.peek_token_is(Kind::Semi) {
                 self.next_token();
             }
         }
+        exprs.extend(self.trivia());
         self.peek_token_expect(Kind::Rb)?;
         Ok(exprs)
     }
 
-    fn parse_request_literal(&mut self) -> Result<(String, String, Vec<Expr>), String> {
+    /// The comments before the next token when comments are kept, the first one may end the line of the current token.
+    fn trivia(&mut self) -> Vec<Expr> {
+        let mut comments = Vec::new();
+        if self.trivia {
+            if let Some(comment) = self.take_trailing() {
+                comments.push(Expr::Comment(comment.literal, true));
+            }
+            if let Some(peek) = self.peek_token().cloned() {
+                let leading = self.take_comments(&peek);
+                comments.extend(leading.into_iter().map(|comment| Expr::Comment(comment.literal, false)));
+            }
+        }
+        comments
+    }
+
+    pub(crate) fn parse_request_literal(&mut self) -> Result<(String, Declaration), String> {
         self.peek_token_expect(Kind::Ident)?;
         let name = self.parse_current_string();
+        let mut options = Vec::new();
+        if self.peek_token_is(Kind::Lp) {
+            self.next_token();
+            options = self.parse_request_options()?;
+        }
         self.peek_token_expect(Kind::Template)?;
         let message = self.parse_current_string();
         let mut asserts = Vec::new();
@@ -317,10 +423,31 @@ impl Parser {
             self.next_token();
             asserts = self.parse_expr_list(Kind::Rs)?;
         }
-        Ok((name, message, asserts))
+        Ok((name, (message, asserts, options)))
+    }
+
+    /// Parses the options of a request declaration, like `(redirects: 0, timeout: 1000)`.
+    fn parse_request_options(&mut self) -> Result<Vec<(String, Expr)>, String> {
+        // a comment among the options has no name
+        let comments = |comments: Vec<Expr>| comments.into_iter().map(|comment| (String::new(), comment));
+        let mut options = Vec::new();
+        while !self.peek_token_is(Kind::Rp) {
+            options.extend(comments(self.trivia()));
+            self.peek_token_expect(Kind::Ident)?;
+            let name = self.parse_current_string();
+            self.peek_token_expect(Kind::Colon)?;
+            self.next_token();
+            options.push((name, self.parse_expr(u8::MIN)?));
+            if !self.peek_token_is(Kind::Rp) {
+                self.peek_token_expect(Kind::Comma)?;
+            }
+        }
+        options.extend(comments(self.trivia()));
+        self.peek_token_expect(Kind::Rp)?;
+        Ok(options)
     }
 
-    fn parse_test_literal(&mut self) -> Result<(String, Vec<Expr>), String> {
+    pub(crate) fn parse_test_literal(&mut self) -> Result<(String, Vec<Expr>), String> {
         self.peek_token_expect(Kind::Ident)?;
         let name = self.parse_current_string();
         let block = self.parse_block_expr()?;
@@ -456,6 +583,54 @@ fn test_parse_string_literal() {
     }
 }
 
+#[test]
+fn test_parse_template_literal() {
+    let tests = vec![
+        ("`hello {name}`;", "hello {name}"),
+        ("`\n{\"name\": \"{name}\"}\n`", "\n{\"name\": \"{name}\"}\n"),
+    ];
+    for (text, expected) in tests {
+        match Parser::new(text).parse() {
+            Ok(Source { exprs, .. }) => {
+                assert!(exprs.len() == 1);
+                if let Some(Expr::Template(value)) = exprs.first() {
+                    assert_eq!(value, expected);
+                } else {
+                    unreachable!("template literal parse failed")
+                }
+            }
+            Err(error) => {
+                unreachable!("{}", error)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_string_display() {
+    let tests = vec![
+        (r#""^\d{4}$""#, r#""^\d{4}$""#),
+        (r#""back\\n""#, r#""back\\n""#),
+        (r#"r"C:\new""#, r#"r"C:\new""#),
+        (r##"r#"say "hi""#"##, r##"r#"say "hi""#"##),
+        (r###"r##"a "#b"##"###, r###"r##"a "#b"##"###),
+        (r##"r#"no quotes"#"##, r##"r#"no quotes"#"##),
+    ];
+    for (text, expected) in tests {
+        match Parser::new(text).parse() {
+            Ok(Source { exprs, .. }) => {
+                let actual = exprs.first().map(|expr| expr.to_string()).unwrap_or_default();
+                assert_eq!(actual, expected);
+                let Source { exprs: reparsed, .. } = Parser::new(&actual).parse().unwrap();
+                assert!(reparsed == exprs);
+            }
+            Err(error) => {
+                unreachable!("{}", error)
+            }
+        }
+    }
+}
+
 #[test]
 fn test_parse_unary_expr() {
     let tests = vec![
@@ -937,10 +1112,11 @@ fn test_parse_request_literal() {
         match Parser::new(text).parse() {
             Ok(Source { requests, .. }) => {
                 assert!(requests.len() == expected_len);
-                if let Some((name, (message, asserts))) = requests.into_iter().next() {
+                if let Some((name, (message, asserts, options))) = requests.into_iter().next() {
                     assert!(name == expected_name);
                     assert!(message == expected_message);
                     assert!(asserts.is_empty());
+                    assert!(options.is_empty());
                 } else {
                     unreachable!("requests none")
                 }
@@ -968,7 +1144,7 @@ fn test_parse_request_asserts() {
     for (text, expected_len, expected_asserts) in tests {
         match Parser::new(text).parse() {
             Ok(Source { requests, .. }) => {
-                if let Some((_, (_, asserts))) = requests.into_iter().next() {
+                if let Some((_, (_, asserts, _))) = requests.into_iter().next() {
                     assert!(asserts.len() == expected_len);
                     assert!(asserts.iter().map(|assert| assert.to_string()).collect::<Vec<String>>() == expected_asserts);
                 } else {
@@ -982,6 +1158,34 @@ fn test_parse_request_asserts() {
     }
 }
 
+#[test]
+fn test_parse_request_options() {
+    let tests = vec![
+        ("request get(redirects: 0)`GET`", vec!["redirects: 0"]),
+        (
+            "request get(redirects: max + 1, other: \"a\",)`GET`[]",
+            vec!["redirects: max + 1", "other: \"a\""],
+        ),
+        ("request get()`GET`", vec![]),
+    ];
+    for (text, expected_options) in tests {
+        match Parser::new(text).parse() {
+            Ok(Source { requests, .. }) => {
+                let (_, _, options) = requests.get("get").unwrap();
+                let options = options
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect::<Vec<String>>();
+                assert_eq!(options, expected_options);
+            }
+            Err(error) => {
+                unreachable!("{}", error)
+            }
+        }
+    }
+    assert!(Parser::new("request get(redirects 0)`GET`").parse().is_err());
+}
+
 #[test]
 fn test_parse_test_literal() {
     let tests = vec![
@@ -1017,3 +1221,18 @@ fn test_parse_test_literal() {
         }
     }
 }
+
+#[test]
+fn test_parse_duplicates() {
+    let text = "request get`GET http://a`\ntest call { get() }\nrequest get`GET http://b`\n  test call {}\ntest other {}";
+    let mut parser = Parser::new(text);
+    let Source { requests, tests, .. } = parser.parse().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(tests.len(), 2);
+    let duplicates = parser
+        .duplicates()
+        .iter()
+        .map(|(keyword, name)| (keyword.literal.as_str(), name.literal.as_str(), name.line, name.column))
+        .collect::<Vec<_>>();
+    assert_eq!(duplicates, vec![("request", "get", 3, 9), ("test", "call", 4, 8)]);
+}
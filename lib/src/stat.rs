@@ -9,20 +9,24 @@ struct Stat {
     avg: u128,
     max: u128,
     min: u128,
+    /// Time throttling held the requests back, apart from their own
+    throttle: u128,
 }
 
 impl Stat {
-    pub fn new(millis: u128) -> Self {
+    pub fn new(millis: u128, throttle: u128) -> Self {
         Stat {
             count: 1,
             sum: millis,
             avg: millis,
             max: millis,
             min: millis,
+            throttle,
         }
     }
-    pub fn add(&mut self, millis: u128) {
+    pub fn add(&mut self, millis: u128, throttle: u128) {
         self.count += 1;
+        self.throttle += throttle;
         self.sum += millis;
         self.avg = self.sum / self.count;
         if millis > self.max {
@@ -40,7 +44,11 @@ impl Display for Stat {
             f,
             "count={}  sum={}  avg={}  max={}  min={}",
             self.count, self.sum, self.avg, self.max, self.min
-        )
+        )?;
+        if self.throttle > 0 {
+            write!(f, "  throttle={}", self.throttle)?;
+        }
+        Ok(())
     }
 }
 
@@ -50,13 +58,13 @@ pub struct Stats {
 }
 
 impl Stats {
-    pub fn add(&mut self, name: &String, millis: u128) {
+    pub fn add(&mut self, name: &String, millis: u128, throttle: u128) {
         match self.stats.get_mut(name) {
             Some(stat) => {
-                stat.add(millis);
+                stat.add(millis, throttle);
             }
             None => {
-                self.stats.insert(name.to_owned(), Stat::new(millis));
+                self.stats.insert(name.to_owned(), Stat::new(millis, throttle));
             }
         };
     }
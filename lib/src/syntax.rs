@@ -12,6 +12,10 @@ pub enum Expr {
     Float(f64),
     Boolean(bool),
     String(String),
+    // Raw string literal and the hashes around it (r#"say "hi""#)
+    Raw(String, usize),
+    // Template literal with interpolated placeholders (`Hello {name}`)
+    Template(String),
     Array(Vec<Expr>),
     Map(Vec<(Expr, Expr)>),
     Index(Box<Expr>, Box<Expr>),
@@ -24,6 +28,8 @@ pub enum Expr {
     Paren(Box<Expr>),
     If(Box<Expr>, Vec<Expr>, Vec<Expr>),
     Call(String, Vec<Expr>),
+    // Comment kept only when parsing for the formatter (// text), and whether it ends the line of what is before it
+    Comment(String, bool),
     // TODO Break A break, with an optional label to break and an optional expr.
     // TODO For A for loop: for pat in expr { ... }.
     // TODO Range A range expr: 1..2, 1.., ..2, 1..=2, ..=2.
@@ -36,7 +42,7 @@ impl Expr {
             Expr::Integer(integer) => Value::Integer(*integer),
             Expr::Float(float) => Value::Float(*float),
             Expr::Boolean(boolean) => Value::Boolean(*boolean),
-            Expr::String(string) => Value::String(string.clone()),
+            Expr::String(string) | Expr::Raw(string, _) => Value::String(string.clone()),
             Expr::Array(items) => Value::Array(items.iter().map(|e| e.eval()).collect::<Vec<Value>>()),
             Expr::Map(pairs) => Value::Map(
                 pairs
@@ -73,6 +79,8 @@ impl Display for Expr {
             Expr::Float(float) => write!(f, "{}", float),
             Expr::Boolean(boolean) => write!(f, "{}", boolean),
             Expr::String(string) => write!(f, "\"{}\"", string),
+            Expr::Raw(string, hashes) => write!(f, "r{}\"{}\"{}", "#".repeat(*hashes), string, "#".repeat(*hashes)),
+            Expr::Template(template) => write!(f, "`{}`", template),
             Expr::Ident(ident) => write!(f, "{}", ident),
             Expr::Array(items) => write!(f, "[{}]", join!(items, "{}", ", ")),
             Expr::Map(pairs) => write!(f, "{{{}}}", join!(pairs, "{}", ": ", ", ")),
@@ -94,6 +102,7 @@ impl Display for Expr {
                 write!(f, " }}")
             }
             Expr::Call(function, arguments) => write!(f, "{}({})", function, join!(arguments, "{}", ", ")),
+            Expr::Comment(comment, _) => write!(f, "//{}", comment),
         }
     }
 }
@@ -6,6 +6,7 @@ use std::fmt::Result;
 pub enum Kind {
     Illegal, // illegal token
     Eof,     // end of file
+    Comment, // // comment
 
     // ident + literal
     Ident,    // add, foobar, x, y, ...
@@ -14,6 +15,7 @@ pub enum Kind {
     True,     // true
     False,    // false
     String,   // "foobar"
+    Raw,      // r#"foo "bar""#
     Template, // `GET http://example.com`
 
     // operator
@@ -60,15 +62,29 @@ pub enum Kind {
     Test,    // test
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Eq)]
 pub struct Token {
     pub kind: Kind,
     pub literal: String,
+    /// The token's line, starting at 1
+    pub line: usize,
+    /// The token's column in chars, starting at 1
+    pub column: usize,
 }
 
 impl Token {
-    pub fn new(kind: Kind, literal: String) -> Token {
-        Token { kind, literal }
+    pub fn new(kind: Kind, literal: String, line: usize, column: usize) -> Token {
+        Token {
+            kind,
+            literal,
+            line,
+            column,
+        }
+    }
+
+    /// The line the token ends on, strings and templates may span several lines.
+    pub(crate) fn end(&self) -> usize {
+        self.line + self.literal.matches('\n').count()
     }
 
     pub fn precedence(&self) -> u8 {
@@ -101,6 +117,13 @@ impl Token {
     }
 }
 
+impl PartialEq for Token {
+    /// Tokens are equal regardless of where they appear in the text.
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.literal == other.literal
+    }
+}
+
 impl Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(f, "{}", self.literal)
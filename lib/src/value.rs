@@ -20,6 +20,7 @@ pub enum Value {
     Float(f64),
     Boolean(bool),
     String(String),
+    Bytes(Vec<u8>),
     Array(Vec<Value>),
     Map(HashMap<String, Value>),
 }
@@ -32,6 +33,7 @@ impl Display for Value {
             Value::Float(float) => write!(f, "{}", float),
             Value::Boolean(boolean) => write!(f, "{}", boolean),
             Value::String(string) => write!(f, "{}", string),
+            Value::Bytes(bytes) => write!(f, "{}", String::from_utf8_lossy(bytes)),
             Value::Array(items) => write!(f, "{:?}", items),
             Value::Map(pairs) => write!(f, "{:?}", pairs),
         }
@@ -47,6 +49,10 @@ impl Add for Value {
             (Value::Integer(left), Value::Float(right)) => Ok(Value::Float(left as f64 + right)),
             (Value::Float(left), Value::Integer(right)) => Ok(Value::Float(left + right as f64)),
             (Value::String(left), Value::String(right)) => Ok(Value::String(left + &right)),
+            (Value::Bytes(mut left), Value::Bytes(right)) => {
+                left.extend(right);
+                Ok(Value::Bytes(left))
+            }
             (left, right) => Err(format!("type mismatch: {} + {}", left, right)),
         }
     }
@@ -162,6 +168,7 @@ impl PartialOrd for Value {
             (Value::Integer(left), Value::Float(right)) => (*left as f64).partial_cmp(right),
             (Value::Float(left), Value::Integer(right)) => left.partial_cmp(&(*right as f64)),
             (Value::String(left), Value::String(right)) => left.partial_cmp(right),
+            (Value::Bytes(left), Value::Bytes(right)) => left.partial_cmp(right),
             (_, _) => None,
         }
     }
@@ -13,14 +13,18 @@ const META: [(&str, &str); 2] = [
         {"name": "thread", "type": "long"},
         {"name": "number", "type": "long"},
         {"name": "order", "type": "long"},
+        {"name": "attempt", "type": "long"},
         {"name": "time_start", "type": "long"},
         {"name": "time_end", "type": "long"},
         {"name": "time_total", "type": "long"},
         {"name": "time_resolve", "type": "long"},
         {"name": "time_connect", "type": "long"},
+        {"name": "time_proxy", "type": "long"},
+        {"name": "time_tls", "type": "long"},
         {"name": "time_write", "type": "long"},
         {"name": "time_delay", "type": "long"},
         {"name": "time_read", "type": "long"},
+        {"name": "time_throttle", "type": "long"},
         {"name": "request_name", "type": "string"},
         {"name": "request_method", "type": "string"},
         {"name": "request_url", "type": "string"},
@@ -31,7 +35,15 @@ const META: [(&str, &str); 2] = [
         {"name": "response_status", "type": "int"},
         {"name": "response_reason", "type": "string"},
         {"name": "response_headers", "type": {"type": "array", "items": {"type": "array", "items": "string"}}},
-        {"name": "response_body", "type": "string"},
+        {"name": "response_body", "type": "bytes"},
+        {"name": "response_wire_size", "type": "long"},
+        {"name": "response_body_size", "type": "long"},
+        {"name": "response_digest", "type": "string"},
+        {"name": "tls_version", "type": "string"},
+        {"name": "tls_cipher", "type": "string"},
+        {"name": "tls_alpn", "type": "string"},
+        {"name": "tls_subject", "type": "string"},
+        {"name": "tls_expires", "type": "long"},
         {"name": "asserts", "type":
             {
                 "type": "array",
@@ -48,7 +60,8 @@ const META: [(&str, &str); 2] = [
                 }
             }
         },
-        {"name": "error", "type": "string"}
+        {"name": "error", "type": "string"},
+        {"name": "error_kind", "type": "string"}
     ]
     }
 "#,
@@ -75,14 +88,18 @@ impl<W: Write> Writer<W> {
             encode_long(thread as i64, &mut data);
             encode_long(number as i64, &mut data);
             encode_long(order as i64, &mut data);
+            encode_long(record.attempt as i64, &mut data);
             encode_long(record.time.start.as_nanos() as i64, &mut data);
             encode_long(record.time.end.as_nanos() as i64, &mut data);
             encode_long(record.time.total.as_nanos() as i64, &mut data);
             encode_long(record.time.resolve.as_nanos() as i64, &mut data);
             encode_long(record.time.connect.as_nanos() as i64, &mut data);
+            encode_long(record.time.proxy.as_nanos() as i64, &mut data);
+            encode_long(record.time.tls.as_nanos() as i64, &mut data);
             encode_long(record.time.write.as_nanos() as i64, &mut data);
             encode_long(record.time.delay.as_nanos() as i64, &mut data);
             encode_long(record.time.read.as_nanos() as i64, &mut data);
+            encode_long(record.time.throttle.as_nanos() as i64, &mut data);
             encode_bytes(record.name.as_bytes(), &mut data);
             encode_bytes(record.request.method.as_ref(), &mut data);
             encode_bytes(record.request.url.to_string().as_bytes(), &mut data);
@@ -103,7 +120,16 @@ impl<W: Write> Writer<W> {
                 encode_bytes(header.name.as_bytes(), &mut data);
                 encode_bytes(header.value.as_bytes(), &mut data);
             }
-            encode_bytes(record.response.body.as_bytes(), &mut data);
+            encode_bytes(&record.response.body, &mut data);
+            encode_long(record.response.wire as i64, &mut data);
+            encode_long(record.response.size as i64, &mut data);
+            encode_bytes(record.response.digest.as_deref().unwrap_or_default().as_bytes(), &mut data);
+            let session = record.response.tls.to_owned().unwrap_or_default();
+            encode_bytes(session.version.as_bytes(), &mut data);
+            encode_bytes(session.cipher.as_bytes(), &mut data);
+            encode_bytes(session.alpn.as_bytes(), &mut data);
+            encode_bytes(session.subject.as_bytes(), &mut data);
+            encode_long(session.expires.as_nanos() as i64, &mut data);
             encode_long(record.asserts.len() as i64, &mut data);
             for assert in record.asserts.iter() {
                 encode_bytes(assert.expr.as_bytes(), &mut data);
@@ -113,6 +139,7 @@ impl<W: Write> Writer<W> {
                 encode_bool(assert.result, &mut data);
             }
             encode_bytes(record.error.as_bytes(), &mut data);
+            encode_bytes(record.kind.as_ref(), &mut data);
         }
         let mut buffer = Vec::new();
         encode_long(records.len() as i64, &mut buffer);
@@ -199,8 +226,10 @@ fn test_writer() {
         response: crate::http::Response::default(),
         asserts: Vec::new(),
         error: String::default(),
+        kind: crate::http::Fault::default(),
+        attempt: 1,
     };
-    writer.write(&vec![record], "test", 0, 0);
+    writer.write(&[record], "test", 0, 0);
     let encoded = writer.w;
     let reader = avro::Reader::new(std::io::Cursor::new(encoded)).unwrap();
     println!("schema:{:?}", reader.reader_schema());